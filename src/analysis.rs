@@ -0,0 +1,448 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{Arg, Instruction, Op, VmError, VM};
+
+/// Upper bound on how many entries are read from a recovered jump table.
+const MAX_DISPATCH_ENTRIES: usize = 256;
+
+/// Words per row of `dump_memory`.
+const DUMP_ROW_WORDS: usize = 8;
+
+/// The 64-bit FNV-1a parameters `program_checksum` hashes with.
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+#[derive(Debug, Default)]
+pub struct ControlFlowGraph {
+    /// Basic blocks as half-open `start..end` address ranges, in address order.
+    pub blocks: Vec<(usize, usize)>,
+    /// Edges between blocks, keyed by the start address of each block.
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl ControlFlowGraph {
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n");
+        for (start, end) in &self.blocks {
+            dot.push_str(&format!("    \"{start}\" [label=\"{start}..{end}\"];\n"));
+        }
+        for (from, to) in &self.edges {
+            dot.push_str(&format!("    \"{from}\" -> \"{to}\";\n"));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// What `find_refs` looks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefTarget {
+    Register(usize),
+    Address(usize),
+}
+
+/// Sweeps `memory` from the start, skipping words that don't decode, and
+/// returns the address of every instruction that refers to `target`. A
+/// register is referred to by any operand naming it, read or written. An
+/// address is referred to by a `rmem` or `wmem` with it as a literal address
+/// operand, and also by any `rmem` or `wmem` whose address is in a register,
+/// since that might be it.
+pub fn find_refs(memory: &[u16], target: RefTarget) -> Vec<usize> {
+    let mut refs = Vec::new();
+    let mut addr = 0;
+
+    while addr < memory.len() {
+        let Ok((inst, next)) = Instruction::decode(memory, addr) else {
+            addr += 1;
+            continue;
+        };
+        let address_operand = match inst.op {
+            Op::Rmem => inst.b,
+            Op::Wmem => inst.a,
+            _ => None,
+        };
+        let refers = match target {
+            RefTarget::Register(reg) => [inst.a, inst.b, inst.c]
+                .into_iter()
+                .any(|arg| matches!(arg, Some(Arg::Register(operand)) if operand == reg)),
+            RefTarget::Address(target) => match address_operand {
+                Some(Arg::Literal(operand)) => operand as usize == target,
+                Some(Arg::Register(_)) => true,
+                None => false,
+            },
+        };
+        if refers {
+            refs.push(addr);
+        }
+        addr = next;
+    }
+
+    refs
+}
+
+impl Instruction {
+    /// Returns the statically known addresses control can move to after this
+    /// instruction, and whether the instruction ends a basic block.
+    fn successors(&self, next: usize) -> (Vec<usize>, bool) {
+        let target = |arg: &Option<Arg>| match arg {
+            Some(Arg::Literal(addr)) => Some(*addr as usize),
+            _ => None,
+        };
+
+        match self.op {
+            Op::Halt | Op::Ret => (vec![], true),
+            Op::Jmp => (target(&self.a).into_iter().collect(), true),
+            Op::Call => (target(&self.a).into_iter().chain([next]).collect(), true),
+            Op::Jt | Op::Jf => (target(&self.b).into_iter().chain([next]).collect(), true),
+            _ => (vec![next], false),
+        }
+    }
+}
+
+impl VM {
+    /// Recovers the candidate targets of a computed jump starting at `addr`,
+    /// either `rmem rX BASE; jmp rX` or `add rI rJ BASE; rmem rX rI; jmp rX`.
+    /// Table entries are read from `BASE` for as long as they point at
+    /// decodable instructions.
+    pub fn dispatch_targets(&self, addr: usize) -> Result<Vec<usize>, VmError> {
+        let not_dispatch = || VmError::NoDispatch { addr };
+
+        let (first, next) = self.decode_at(addr)?;
+        let (index_reg, offset, next) = match first {
+            Instruction {
+                op: Op::Add,
+                a: Some(Arg::Register(reg)),
+                b: Some(Arg::Literal(base)),
+                c: Some(Arg::Register(_)),
+            }
+            | Instruction {
+                op: Op::Add,
+                a: Some(Arg::Register(reg)),
+                b: Some(Arg::Register(_)),
+                c: Some(Arg::Literal(base)),
+            } => (Some(reg), base, next),
+            _ => (None, 0, addr),
+        };
+
+        let (rmem, next) = self.decode_at(next)?;
+        let (target_reg, base) = match (rmem, index_reg) {
+            (
+                Instruction {
+                    op: Op::Rmem,
+                    a: Some(Arg::Register(target)),
+                    b: Some(Arg::Literal(base)),
+                    ..
+                },
+                None,
+            ) => (target, base),
+            (
+                Instruction {
+                    op: Op::Rmem,
+                    a: Some(Arg::Register(target)),
+                    b: Some(Arg::Register(reg)),
+                    ..
+                },
+                Some(index),
+            ) if reg == index => (target, offset),
+            _ => return Err(not_dispatch()),
+        };
+
+        match self.decode_at(next)? {
+            (
+                Instruction {
+                    op: Op::Jmp,
+                    a: Some(Arg::Register(reg)),
+                    ..
+                },
+                _,
+            ) if reg == target_reg => (),
+            _ => return Err(not_dispatch()),
+        }
+
+        Ok(self.memory[(base as usize).min(self.memory.len())..]
+            .iter()
+            .take(MAX_DISPATCH_ENTRIES)
+            .map(|&target| target as usize)
+            .take_while(|&target| self.decode_at(target).is_ok())
+            .collect())
+    }
+
+    /// Lists each contiguous run of non-zero memory as `start..end: words`, with
+    /// the words in hex.
+    pub fn dump_nonzero(&self) -> String {
+        let mut dump = String::new();
+        let mut addr = 0;
+
+        while addr < self.memory.len() {
+            if self.memory[addr] == 0 {
+                addr += 1;
+                continue;
+            }
+
+            let start = addr;
+            while addr < self.memory.len() && self.memory[addr] != 0 {
+                addr += 1;
+            }
+            let words: Vec<String> = self.memory[start..addr]
+                .iter()
+                .map(|word| format!("{word:04x}"))
+                .collect();
+            dump.push_str(&format!("{start}..{addr}: {}\n", words.join(" ")));
+        }
+
+        dump
+    }
+
+    /// Formats `len` words from `start` as a hex dump, eight words a row with
+    /// the address on the left and printable ASCII on the right. The region
+    /// is clamped to the end of memory.
+    pub fn dump_memory(&self, start: usize, len: usize) -> String {
+        let start = start.min(self.memory.len());
+        let end = start.saturating_add(len).min(self.memory.len());
+        let mut dump = String::new();
+
+        for (row, words) in self.memory[start..end].chunks(DUMP_ROW_WORDS).enumerate() {
+            let hex: Vec<String> = words.iter().map(|word| format!("{word:04x}")).collect();
+            let text: String = words
+                .iter()
+                .map(|&word| match u8::try_from(word) {
+                    Ok(byte) if byte.is_ascii_graphic() || byte == b' ' => char::from(byte),
+                    _ => '.',
+                })
+                .collect();
+            let addr = start + row * DUMP_ROW_WORDS;
+            dump.push_str(&format!(
+                "{addr:04x}: {:width$}  |{text}|\n",
+                hex.join(" "),
+                width = DUMP_ROW_WORDS * 5 - 1
+            ));
+        }
+
+        dump
+    }
+
+    /// Scans the whole of memory and returns every literal operand passed to
+    /// `op`, along with the address of the operand word.
+    pub fn literal_operands(&self, op: Op) -> Vec<(usize, u16)> {
+        let mut literals = Vec::new();
+        let mut addr = 0;
+
+        while addr < self.memory.len() {
+            let Ok((inst, next)) = self.decode_at(addr) else {
+                addr += 1;
+                continue;
+            };
+            if inst.op == op {
+                for (offset, arg) in [inst.a, inst.b, inst.c].into_iter().enumerate() {
+                    if let Some(Arg::Literal(value)) = arg {
+                        literals.push((addr + 1 + offset, value));
+                    }
+                }
+            }
+            addr = next;
+        }
+
+        literals
+    }
+
+    /// Returns, in address order, every `call` target whose body calls itself
+    /// and reads `r7`: the shape of the teleporter's confirmation routine,
+    /// which recurses on `r0` and `r1` with `r7` as a hidden parameter. The
+    /// body is everything reachable from the entry without following calls.
+    pub fn confirmation_candidates(&self) -> Vec<usize> {
+        let entries: BTreeSet<usize> = self
+            .literal_operands(Op::Call)
+            .into_iter()
+            .map(|(_, target)| target as usize)
+            .collect();
+
+        entries
+            .into_iter()
+            .filter(|&entry| {
+                let mut visited = BTreeSet::new();
+                let mut pending = vec![entry];
+                let (mut recurses, mut reads_r7) = (false, false);
+
+                while let Some(addr) = pending.pop() {
+                    if !visited.insert(addr) {
+                        continue;
+                    }
+                    let Ok((inst, next)) = self.decode_at(addr) else {
+                        continue;
+                    };
+                    reads_r7 |= [inst.a, inst.b, inst.c]
+                        .into_iter()
+                        .skip(usize::from(inst.op.writes_register()))
+                        .flatten()
+                        .any(|arg| matches!(arg, Arg::Register(7)));
+
+                    if inst.op == Op::Call {
+                        recurses |= matches!(inst.a, Some(Arg::Literal(target)) if target as usize == entry);
+                        pending.push(next);
+                    } else {
+                        pending.extend(inst.successors(next).0);
+                    }
+                }
+
+                recurses && reads_r7
+            })
+            .collect()
+    }
+
+    /// Every address where memory holds `pattern`, in ascending order.
+    /// Matches may overlap; an empty pattern matches nowhere.
+    pub fn search(&self, pattern: &[u16]) -> Vec<usize> {
+        if pattern.is_empty() {
+            return Vec::new();
+        }
+        self.memory
+            .windows(pattern.len())
+            .enumerate()
+            .filter(|(_, window)| *window == pattern)
+            .map(|(addr, _)| addr)
+            .collect()
+    }
+
+    /// An FNV-1a hash of memory up to its last non-zero word, as
+    /// little-endian bytes, for telling program images apart.
+    pub fn program_checksum(&self) -> u64 {
+        let end = self
+            .memory
+            .iter()
+            .rposition(|&word| word != 0)
+            .map_or(0, |last| last + 1);
+        self.memory[..end]
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// Decodes linearly from address 0 until a word fails to decode, returning
+    /// how many instructions were read and the address where decoding broke.
+    /// This roughly bounds the code section ahead of the program's data.
+    pub fn code_extent(&self) -> (usize, usize) {
+        let mut count = 0;
+        let mut addr = 0;
+        while let Ok((_, next)) = self.decode_at(addr) {
+            count += 1;
+            addr = next;
+        }
+        (count, addr)
+    }
+
+    /// Finds runs of at least `min_len` back-to-back `out` instructions with
+    /// literal operands and returns each run's address and the text it prints.
+    pub fn out_literal_runs(&self, min_len: usize) -> Vec<(usize, String)> {
+        let mut runs = Vec::new();
+        let mut run: Option<(usize, String)> = None;
+        let mut addr = 0;
+
+        while addr < self.memory.len() {
+            match self.decode_at(addr) {
+                Ok((
+                    Instruction {
+                        op: Op::Out,
+                        a: Some(Arg::Literal(value)),
+                        ..
+                    },
+                    next,
+                )) => {
+                    run.get_or_insert((addr, String::new()))
+                        .1
+                        .push(char::from(value as u8));
+                    addr = next;
+                }
+                _ => {
+                    runs.extend(
+                        run.take()
+                            .filter(|(_, text)| text.chars().count() >= min_len),
+                    );
+                    addr += 1;
+                }
+            }
+        }
+        runs.extend(run.filter(|(_, text)| text.chars().count() >= min_len));
+
+        runs
+    }
+
+    /// Decodes every instruction reachable from address 0 through fallthrough
+    /// and static jump and call targets, without executing anything, and
+    /// collects a `VmError::DecodeFailed` for each one that doesn't decode.
+    pub fn verify(&self) -> Result<(), Vec<VmError>> {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![0];
+        let mut errors = BTreeMap::new();
+
+        while let Some(addr) = pending.pop() {
+            if addr >= self.memory.len() || !visited.insert(addr) {
+                continue;
+            }
+            match self.decode_at(addr) {
+                Ok((inst, next)) => pending.extend(inst.successors(next).0),
+                Err(cause) => {
+                    errors.insert(addr, cause);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(errors
+            .into_iter()
+            .map(|(addr, cause)| VmError::DecodeFailed {
+                addr,
+                cause: Box::new(cause),
+            })
+            .collect())
+    }
+
+    /// Recursively walks the code reachable from `entry`, following every
+    /// statically known jump and call target, and splits it into basic blocks.
+    pub fn control_flow_graph(&self, entry: usize) -> ControlFlowGraph {
+        let mut instructions = BTreeMap::new();
+        let mut leaders = BTreeSet::from([entry]);
+        let mut pending = vec![entry];
+
+        while let Some(addr) = pending.pop() {
+            if instructions.contains_key(&addr) {
+                continue;
+            }
+            let Ok((inst, next)) = self.decode_at(addr) else {
+                continue;
+            };
+
+            let (successors, ends_block) = inst.successors(next);
+            if ends_block {
+                leaders.extend(&successors);
+                leaders.insert(next);
+            }
+            pending.extend(&successors);
+            instructions.insert(addr, (next, successors, ends_block));
+        }
+
+        let mut cfg = ControlFlowGraph::default();
+        let mut block_start = None;
+
+        for (&addr, (next, successors, ends_block)) in &instructions {
+            let start = *block_start.get_or_insert(addr);
+            let falls_into_leader = leaders.contains(next) || !instructions.contains_key(next);
+
+            if *ends_block || falls_into_leader {
+                cfg.blocks.push((start, *next));
+                cfg.edges.extend(
+                    successors
+                        .iter()
+                        .filter(|target| instructions.contains_key(target))
+                        .map(|&target| (start, target)),
+                );
+                block_start = None;
+            }
+        }
+
+        cfg
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{Instruction, Op, MAX_INSTRUCTION_WORDS, MODULUS, OPCODES, VM};
+
+/// Parses an operand written the way the disassembler prints it: `r0`..`r7`
+/// or a decimal literal below 32768.
+fn parse_operand(text: &str) -> Result<u16, String> {
+    if let Some(reg) = text.strip_prefix('r') {
+        return match reg.parse::<u16>() {
+            Ok(reg) if reg < 8 => Ok(MODULUS + reg),
+            _ => Err(format!("invalid register: {text}")),
+        };
+    }
+
+    match text.parse::<u16>() {
+        Ok(value) if value < MODULUS => Ok(value),
+        _ => Err(format!("invalid literal: {text}")),
+    }
+}
+
+/// Assembles disassembler-style source, one instruction or `db WORD` per
+/// line, into the little-endian image `VM::load` expects. A leading `ADDR:`
+/// on a line is ignored, so `disassemble` output assembles back as-is.
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    Ok(assemble_words(source)?
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .collect())
+}
+
+/// Like `assemble`, but returns the words themselves.
+pub(crate) fn assemble_words(source: &str) -> Result<Vec<u16>, String> {
+    let mut words = Vec::new();
+
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split_once(": ").map_or(line, |(_, rest)| rest);
+        let mut tokens = line.split_whitespace();
+        let Some(mnemonic) = tokens.next() else {
+            continue;
+        };
+        let operands: Vec<&str> = tokens.collect();
+        let error = |message: String| format!("line {}: {message}", number + 1);
+
+        if mnemonic == "db" {
+            let [value] = operands[..] else {
+                return Err(error("db takes a single word".to_string()));
+            };
+            words.push(
+                value
+                    .parse()
+                    .map_err(|_| error(format!("invalid word: {value}")))?,
+            );
+            continue;
+        }
+
+        let spec = OPCODES
+            .iter()
+            .find(|spec| spec.mnemonic == mnemonic)
+            .ok_or_else(|| error(format!("unknown mnemonic: {mnemonic}")))?;
+        if operands.len() != spec.arity {
+            return Err(error(format!(
+                "{mnemonic} takes {} operands, got {}",
+                spec.arity,
+                operands.len()
+            )));
+        }
+
+        words.push(spec.code);
+        for operand in operands {
+            words.push(parse_operand(operand).map_err(error)?);
+        }
+    }
+
+    Ok(words)
+}
+
+impl VM {
+    /// Assembles `source`, a single instruction such as `add r0 r0 1`, and
+    /// writes it over the instruction at `addr`. It must be no longer than
+    /// the instruction it replaces (one word, if that doesn't decode), so the
+    /// next one is left intact; any words it leaves over become `noop`s.
+    pub fn patch_asm(&mut self, addr: usize, source: &str) -> Result<(), String> {
+        let words = assemble_words(source)?;
+        match Instruction::decode(&words, 0) {
+            Ok((_, len)) if len == words.len() => (),
+            _ => return Err(format!("expected a single instruction: {source}")),
+        }
+
+        let room = match self.decode_at(addr) {
+            Ok((_, next)) => next - addr,
+            Err(_) => 1,
+        };
+        if words.len() > room {
+            return Err(format!(
+                "{source} takes {} words but only {room} are free at {addr}",
+                words.len()
+            ));
+        }
+
+        let noop = Op::Noop.spec().code;
+        for (offset, word) in words
+            .into_iter()
+            .chain([noop; MAX_INSTRUCTION_WORDS])
+            .take(room)
+            .enumerate()
+        {
+            self.poke_mem(addr + offset, word)
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{BufRead, Write};
+
+use crate::{InputSource, VM};
+
+/// Configures a `VM` through chained calls, for when the `with_*`
+/// constructors and setters get unwieldy.
+#[derive(Default)]
+pub struct VmBuilder {
+    vm: VM,
+}
+
+impl VmBuilder {
+    /// Starts from the same defaults as `VM::new`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `in` lines from `input` instead of stdin.
+    pub fn input(mut self, input: Box<dyn BufRead>) -> Self {
+        self.vm.input_sources = vec![InputSource::Input(input)];
+        self
+    }
+
+    /// See `VM::set_raw_input`.
+    pub fn raw_input(mut self, raw: bool) -> Self {
+        self.vm.set_raw_input(raw);
+        self
+    }
+
+    /// See `VM::set_strict_decode`.
+    pub fn strict_decode(mut self, strict: bool) -> Self {
+        self.vm.set_strict_decode(strict);
+        self
+    }
+
+    /// Reads exactly the bytes of `recorded`; see `VM::replay`.
+    pub fn replay(mut self, recorded: &[u8]) -> Self {
+        self.vm.input_sources = vec![InputSource::Queue(recorded.to_vec())];
+        self
+    }
+
+    /// Writes `out` bytes to `output` instead of stdout.
+    pub fn output(mut self, output: Box<dyn Write>) -> Self {
+        self.vm.output = output;
+        self
+    }
+
+    /// See `VM::set_trace`.
+    pub fn trace(mut self, trace: Box<dyn Write>) -> Self {
+        self.vm.set_trace(trace);
+        self
+    }
+
+    /// See `VM::set_max_cycles`.
+    pub fn max_cycles(mut self, limit: u64) -> Self {
+        self.vm.set_max_cycles(limit);
+        self
+    }
+
+    /// See `VM::add_breakpoint`.
+    pub fn breakpoint(mut self, addr: usize) -> Self {
+        self.vm.add_breakpoint(addr);
+        self
+    }
+
+    pub fn build(self) -> VM {
+        self.vm
+    }
+}
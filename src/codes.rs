@@ -0,0 +1,57 @@
+//! Finding the challenge's validation codes in a program image. Only codes
+//! that sit in the image as text can be found this way; the rest are only
+//! computed at runtime.
+
+use crate::Op;
+
+/// Every code is this many ASCII letters and digits.
+const CODE_LEN: usize = 12;
+
+/// Codes are random mixed-case strings, so insisting on a capital after the
+/// first letter keeps ordinary capitalized words out.
+fn is_code(word: &str) -> bool {
+    word.len() == CODE_LEN
+        && word.chars().all(|ch| ch.is_ascii_alphanumeric())
+        && word.chars().skip(1).any(|ch| ch.is_ascii_uppercase())
+        && word.chars().any(|ch| ch.is_ascii_lowercase())
+}
+
+/// Collects the text the image holds as runs of printable ASCII words and
+/// the text it prints with back-to-back `out LITERAL` instructions.
+fn texts(memory: &[u16]) -> Vec<String> {
+    let is_text = |word: u16| word == u16::from(b'\n') || (32..127).contains(&word);
+    let mut texts: Vec<String> = memory
+        .split(|&word| !is_text(word))
+        .map(|run| run.iter().map(|&word| char::from(word as u8)).collect())
+        .collect();
+
+    let out = Op::Out.spec().code;
+    let mut printed = String::new();
+    let mut addr = 0;
+    while addr + 1 < memory.len() {
+        if memory[addr] == out && is_text(memory[addr + 1]) {
+            printed.push(char::from(memory[addr + 1] as u8));
+            addr += 2;
+        } else {
+            texts.push(std::mem::take(&mut printed));
+            addr += 1;
+        }
+    }
+    texts.push(printed);
+
+    texts
+}
+
+/// Returns every string of the codes' format found in `memory`, in the
+/// order they appear and without duplicates.
+pub fn scan_binary_for_codes(memory: &[u16]) -> Vec<String> {
+    let mut codes: Vec<String> = Vec::new();
+    for text in texts(memory) {
+        for word in text.split(|ch: char| !ch.is_ascii_alphanumeric()) {
+            if is_code(word) && !codes.iter().any(|code| code == word) {
+                codes.push(word.to_string());
+            }
+        }
+    }
+    codes
+}
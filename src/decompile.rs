@@ -0,0 +1,167 @@
+//! An experimental decompiler that renders a single routine as pseudo-C.
+
+use std::collections::BTreeSet;
+
+use crate::{Arg, Instruction, Op, MODULUS};
+
+/// Decodes from `start` until a `ret`, `jmp` or `halt` that no earlier branch
+/// jumps past. Also returns the address of the word that stopped decoding
+/// early, if one did.
+fn routine_body(memory: &[u16], start: usize) -> (Vec<(usize, Instruction)>, Option<usize>) {
+    let mut body = Vec::new();
+    let mut reach = start;
+    let mut addr = start;
+
+    loop {
+        let Ok((inst, next)) = Instruction::decode(memory, addr) else {
+            return (body, Some(addr));
+        };
+        body.push((addr, inst));
+        if let Some(target) = branch_target(&inst) {
+            reach = reach.max(target);
+        }
+        if matches!(inst.op, Op::Ret | Op::Jmp | Op::Halt) && next > reach {
+            return (body, None);
+        }
+        addr = next;
+    }
+}
+
+/// The literal target of a `jmp`, `jt` or `jf`.
+fn branch_target(inst: &Instruction) -> Option<usize> {
+    let target = match inst.op {
+        Op::Jmp => inst.a,
+        Op::Jt | Op::Jf => inst.b,
+        _ => None,
+    };
+    match target {
+        Some(Arg::Literal(target)) => Some(target as usize),
+        _ => None,
+    }
+}
+
+/// Renders one instruction as a statement, with jumps as `goto`s.
+fn statement(inst: &Instruction) -> String {
+    let [a, b, c] =
+        [inst.a, inst.b, inst.c].map(|arg| arg.map(|arg| arg.to_string()).unwrap_or_default());
+    let goto = |target: &str| match branch_target(inst) {
+        Some(target) => format!("goto L_{target};"),
+        None => format!("goto *{target};"),
+    };
+
+    match inst.op {
+        Op::Halt => "halt();".to_string(),
+        Op::Set => format!("{a} = {b};"),
+        Op::Push => format!("push({a});"),
+        Op::Pop => format!("{a} = pop();"),
+        Op::Eq => format!("{a} = {b} == {c};"),
+        Op::Gt => format!("{a} = {b} > {c};"),
+        Op::Jmp => goto(&a),
+        Op::Jt => format!("if ({a} != 0) {}", goto(&b)),
+        Op::Jf => format!("if ({a} == 0) {}", goto(&b)),
+        Op::Add => format!("{a} = ({b} + {c}) % {MODULUS};"),
+        Op::Mult => format!("{a} = ({b} * {c}) % {MODULUS};"),
+        Op::Mod => format!("{a} = {b} % {c};"),
+        Op::And => format!("{a} = {b} & {c};"),
+        Op::Or => format!("{a} = {b} | {c};"),
+        Op::Not => format!("{a} = ~{b} & {};", MODULUS - 1),
+        Op::Rmem => format!("{a} = mem[{b}];"),
+        Op::Wmem => format!("mem[{a}] = {b};"),
+        Op::Call => match inst.a {
+            Some(Arg::Literal(target)) => format!("fn_{target}();"),
+            _ => format!("(*{a})();"),
+        },
+        Op::Ret => "return;".to_string(),
+        Op::Out => format!("out({a});"),
+        Op::In => format!("{a} = in();"),
+        Op::Noop => "// noop".to_string(),
+    }
+}
+
+struct Emitter<'a> {
+    body: &'a [(usize, Instruction)],
+    /// Addresses that get a label because a `goto` targets them.
+    labels: &'a BTreeSet<usize>,
+    gotos: BTreeSet<usize>,
+    lines: Vec<String>,
+}
+
+impl Emitter<'_> {
+    /// Emits the instructions from `index` up to address `end`, indented by
+    /// `depth`, and returns the index after the last one. A `jt` or `jf` that
+    /// skips forward to an instruction no later than `end` becomes an `if`
+    /// around the instructions it skips; any other jump is a `goto`.
+    fn block(&mut self, mut index: usize, end: usize, depth: usize) -> usize {
+        let indent = "    ".repeat(depth);
+
+        while let Some(&(addr, inst)) = self.body.get(index) {
+            if addr >= end {
+                break;
+            }
+            if self.labels.contains(&addr) {
+                self.lines.push(format!("L_{addr}:"));
+            }
+
+            match branch_target(&inst) {
+                Some(target)
+                    if inst.op != Op::Jmp
+                        && target > addr
+                        && target <= end
+                        && self.body.iter().any(|&(start, _)| start == target) =>
+                {
+                    let cond = inst.a.map(|arg| arg.to_string()).unwrap_or_default();
+                    let test = if inst.op == Op::Jt { "==" } else { "!=" };
+                    self.lines.push(format!("{indent}if ({cond} {test} 0) {{"));
+                    index = self.block(index + 1, target, depth + 1);
+                    self.lines.push(format!("{indent}}}"));
+                }
+                target => {
+                    self.gotos.extend(target);
+                    self.lines.push(format!("{indent}{}", statement(&inst)));
+                    index += 1;
+                }
+            }
+        }
+
+        index
+    }
+}
+
+/// Renders the routine at `start` as indented pseudo-C, up to the `ret` (or
+/// `jmp` or `halt`) that ends it. Assignments, arithmetic, calls and returns
+/// map onto C directly, with registers as variables and memory as `mem`.
+/// Branches that skip forward become `if` blocks and the rest `goto`s. A word
+/// that doesn't decode ends the output with a comment.
+pub fn decompile_routine(memory: &[u16], start: usize) -> String {
+    let (body, failed_at) = routine_body(memory, start);
+
+    // `goto` targets are only known once the structure has been worked out,
+    // so lay it out once to find them and again to label them.
+    let no_labels = BTreeSet::new();
+    let mut emitter = Emitter {
+        body: &body,
+        labels: &no_labels,
+        gotos: BTreeSet::new(),
+        lines: Vec::new(),
+    };
+    emitter.block(0, usize::MAX, 1);
+    let labels = emitter.gotos;
+    let mut emitter = Emitter {
+        body: &body,
+        labels: &labels,
+        gotos: BTreeSet::new(),
+        lines: Vec::new(),
+    };
+    emitter.block(0, usize::MAX, 1);
+
+    let mut text = format!("void fn_{start}() {{\n");
+    for line in emitter.lines {
+        text.push_str(&line);
+        text.push('\n');
+    }
+    if let Some(addr) = failed_at {
+        text.push_str(&format!("    /* can't decode the word at {addr} */\n"));
+    }
+    text.push_str("}\n");
+    text
+}
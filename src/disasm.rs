@@ -0,0 +1,187 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::ops::Range;
+
+use crate::{Arg, Instruction, Op, RegisterAliases, VM};
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Literal(value) => write!(f, "{value}"),
+            Self::Register(reg) => write!(f, "r{reg}"),
+        }
+    }
+}
+
+/// Renders the instruction as assembly, as in `add r0 r1 2`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_instruction(self, &RegisterAliases::default()))
+    }
+}
+
+/// Formats `arg`, naming registers by their `set_register_alias` alias if
+/// they have one.
+pub(crate) fn format_arg(arg: Arg, aliases: &RegisterAliases) -> String {
+    match arg {
+        Arg::Register(reg) => aliases
+            .get(reg)
+            .cloned()
+            .flatten()
+            .unwrap_or_else(|| arg.to_string()),
+        Arg::Literal(_) => arg.to_string(),
+    }
+}
+
+pub(crate) fn format_instruction(inst: &Instruction, aliases: &RegisterAliases) -> String {
+    [inst.a, inst.b, inst.c]
+        .into_iter()
+        .flatten()
+        .fold(inst.op.spec().mnemonic.to_string(), |text, arg| {
+            format!("{text} {}", format_arg(arg, aliases))
+        })
+}
+
+/// The position and value of the literal operand a jump or call transfers
+/// control to.
+fn static_target(inst: &Instruction) -> Option<(usize, usize)> {
+    let (index, arg) = match inst.op {
+        Op::Jmp | Op::Call => (0, inst.a),
+        Op::Jt | Op::Jf => (1, inst.b),
+        _ => return None,
+    };
+    match arg {
+        Some(Arg::Literal(target)) => Some((index, target as usize)),
+        _ => None,
+    }
+}
+
+/// Quotes `text` as a JSON string, escaping quotes, backslashes and control
+/// characters.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for ch in text.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            ch if ch.is_control() => quoted.push_str(&format!("\\u{:04x}", u32::from(ch))),
+            ch => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats one executed instruction as a JSON trace object; see
+/// `TraceFormat::Json`.
+pub(crate) fn format_trace_json(pc: usize, inst: &Instruction, registers: &[u16; 8]) -> String {
+    let args: Vec<String> = [inst.a, inst.b, inst.c]
+        .into_iter()
+        .flatten()
+        .map(|arg| match arg {
+            Arg::Literal(value) => format!("{{\"literal\":{value}}}"),
+            Arg::Register(reg) => format!("{{\"register\":{reg}}}"),
+        })
+        .collect();
+    let regs: Vec<String> = registers.iter().map(u16::to_string).collect();
+    format!(
+        "{{\"pc\":{pc},\"op\":{},\"args\":[{}],\"regs\":[{}]}}",
+        json_string(inst.op.spec().mnemonic),
+        args.join(","),
+        regs.join(",")
+    )
+}
+
+impl VM {
+    /// Disassembles the instructions starting within `addrs`, one line each
+    /// as `ADDR: mnemonic operands`. Words that don't decode become `db`
+    /// directives and disassembly resumes at the next word.
+    pub fn disassemble(&self, addrs: Range<usize>) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut addr = addrs.start;
+
+        while addr < addrs.end.min(self.memory.len()) {
+            match self.decode_at(addr) {
+                Ok((inst, next)) => {
+                    lines.push(format!(
+                        "{addr:04}: {}",
+                        format_instruction(&inst, &self.register_aliases)
+                    ));
+                    addr = next;
+                }
+                Err(_) => {
+                    lines.push(format!("{addr:04}: db {}", self.memory[addr]));
+                    addr += 1;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Like `disassemble`, but every static jump or call target gets a label,
+    /// its name from `symbols` or else `L_ADDR`. Labels are printed as
+    /// `name:` lines before their target and in place of target operands.
+    pub fn disassemble_labeled(
+        &self,
+        addrs: Range<usize>,
+        symbols: &HashMap<usize, String>,
+    ) -> Vec<String> {
+        let end = addrs.end.min(self.memory.len());
+        let mut instructions = Vec::new();
+        let mut addr = addrs.start;
+        while addr < end {
+            match self.decode_at(addr) {
+                Ok((inst, next)) => {
+                    instructions.push((addr, Some(inst)));
+                    addr = next;
+                }
+                Err(_) => {
+                    instructions.push((addr, None));
+                    addr += 1;
+                }
+            }
+        }
+
+        let targets: BTreeSet<usize> = instructions
+            .iter()
+            .filter_map(|(_, inst)| static_target(inst.as_ref()?))
+            .map(|(_, target)| target)
+            .collect();
+        let label = |target: usize| {
+            symbols
+                .get(&target)
+                .cloned()
+                .unwrap_or_else(|| format!("L_{target}"))
+        };
+
+        let mut lines = Vec::new();
+        for (addr, inst) in instructions {
+            if targets.contains(&addr) {
+                lines.push(format!("{}:", label(addr)));
+            }
+            let Some(inst) = inst else {
+                lines.push(format!("{addr:04}: db {}", self.memory[addr]));
+                continue;
+            };
+            let target = static_target(&inst);
+            let text = [inst.a, inst.b, inst.c]
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .fold(
+                    inst.op.spec().mnemonic.to_string(),
+                    |text, (index, arg)| match target {
+                        Some((target_index, target)) if target_index == index => {
+                            format!("{text} {}", label(target))
+                        }
+                        _ => format!("{text} {}", format_arg(arg, &self.register_aliases)),
+                    },
+                );
+            lines.push(format!("{addr:04}: {text}"));
+        }
+
+        lines
+    }
+}
@@ -0,0 +1,155 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::{Instruction, Op};
+
+/// Everything that can go wrong while loading or running a program.
+#[derive(Debug)]
+pub enum VmError {
+    /// A word in an opcode position that is not one of the 22 opcodes.
+    UnknownOpcode(u16),
+    /// An operand above 32775, which is neither a literal nor a register.
+    InvalidArgument(u16),
+    /// A register operand outside `r0`..=`r7`.
+    BadRegister(usize),
+    /// A `set_register_alias` name that isn't an identifier, or that clashes
+    /// with `mem` or a register's own name.
+    InvalidRegisterAlias(String),
+    /// `pop` with nothing on the stack.
+    EmptyStackPop,
+    /// The instruction at `addr` would have grown the stack past `depth`.
+    StackOverflow {
+        addr: usize,
+        depth: usize,
+    },
+    /// `mod` with a zero divisor, at the instruction at `addr`.
+    ModByZero {
+        addr: usize,
+        dividend: u16,
+    },
+    /// An access to an address past the end of memory, by the instruction
+    /// `op` when the access came from executing one.
+    OutOfBounds {
+        addr: usize,
+        op: Option<Op>,
+    },
+    /// A decoded instruction whose operands the VM cannot execute, such as a
+    /// literal where a register destination is required.
+    InvalidInstruction {
+        addr: usize,
+        instruction: Instruction,
+    },
+    /// A strictly decoded `op` whose operand at index `operand` must be a
+    /// register but is a literal.
+    ExpectedRegister {
+        op: Op,
+        operand: usize,
+    },
+    /// `verify` found that the instruction at `addr` doesn't decode.
+    DecodeFailed {
+        addr: usize,
+        cause: Box<VmError>,
+    },
+    /// A program image with an odd number of bytes.
+    OddProgramLength(usize),
+    /// A program of this many words, which does not fit in memory.
+    ProgramTooLarge(usize),
+    /// A fault targeting a bit outside a 16-bit word.
+    InvalidFaultBit(u8),
+    /// The address given to `dispatch_targets` is not an `rmem`/`jmp` pair.
+    NoDispatch {
+        addr: usize,
+    },
+    /// A saved state that does not match the format `save_state` writes.
+    InvalidState(&'static str),
+    /// A state file from a format version this build can't read.
+    UnsupportedStateVersion(u8),
+    /// The run outlasted the limit set with `set_time_limit`.
+    TimeLimitExceeded(Duration),
+    /// The run executed as many instructions as `set_max_cycles` allows.
+    CycleLimitExceeded(u64),
+    /// The VM came back to `pc` with the same registers and stack, and no
+    /// memory write or input in between, so it can never make progress.
+    InfiniteLoopDetected {
+        pc: usize,
+    },
+    /// `step_back` with no rewind history left.
+    NothingToRewind,
+    Io(io::Error),
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode(value) => write!(f, "received unknown op code: {value}"),
+            Self::InvalidArgument(value) => write!(f, "unable to convert {value} to argument"),
+            Self::BadRegister(reg) => write!(f, "register r{reg} does not exist"),
+            Self::InvalidRegisterAlias(name) => write!(f, "invalid register alias: {name}"),
+            Self::EmptyStackPop => write!(f, "called pop on an empty stack"),
+            Self::StackOverflow { addr, depth } => {
+                write!(
+                    f,
+                    "stack overflow at {addr}: depth limit of {depth} reached"
+                )
+            }
+            Self::ModByZero { addr, dividend } => {
+                write!(f, "mod by zero at {addr}: {dividend} % 0")
+            }
+            Self::OutOfBounds { addr, op: None } => write!(f, "address {addr} is out of bounds"),
+            Self::OutOfBounds { addr, op: Some(op) } => {
+                write!(
+                    f,
+                    "{} of address {addr} is out of bounds",
+                    op.spec().mnemonic
+                )
+            }
+            Self::InvalidInstruction { addr, instruction } => {
+                write!(f, "unable to handle `{instruction}` at {addr}")
+            }
+            Self::ExpectedRegister { op, operand } => {
+                write!(
+                    f,
+                    "{} expects a register as operand {operand}",
+                    op.spec().mnemonic
+                )
+            }
+            Self::DecodeFailed { addr, cause } => {
+                write!(f, "failed to decode instruction at {addr}: {cause}")
+            }
+            Self::OddProgramLength(len) => {
+                write!(f, "program of {len} bytes is not a whole number of words")
+            }
+            Self::ProgramTooLarge(len) => {
+                write!(f, "program of {len} words does not fit in memory")
+            }
+            Self::InvalidFaultBit(bit) => write!(f, "fault bit {bit} is out of range"),
+            Self::NoDispatch { addr } => write!(f, "no rmem/jmp dispatch at {addr}"),
+            Self::InvalidState(reason) => write!(f, "invalid state file: {reason}"),
+            Self::UnsupportedStateVersion(version) => {
+                write!(f, "unsupported state file version {version}")
+            }
+            Self::TimeLimitExceeded(limit) => write!(f, "time limit of {limit:?} exceeded"),
+            Self::CycleLimitExceeded(limit) => write!(f, "cycle limit of {limit} exceeded"),
+            Self::InfiniteLoopDetected { pc } => write!(f, "infinite loop detected at {pc}"),
+            Self::NothingToRewind => write!(f, "no rewind history to step back through"),
+            Self::Io(err) => write!(f, "i/o error: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for VmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::DecodeFailed { cause, .. } => Some(cause.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for VmError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{MODULUS, VM};
+
+/// Binary operators from loosest to tightest binding.
+const PRECEDENCE: [&[&str]; 5] = [
+    &["==", "!=", "<", ">"],
+    &["|"],
+    &["&"],
+    &["+", "-"],
+    &["*", "%"],
+];
+
+/// A recursive-descent parser that evaluates as it goes.
+struct Evaluator<'a> {
+    vm: &'a VM,
+    text: &'a str,
+}
+
+impl Evaluator<'_> {
+    fn skip_spaces(&mut self) {
+        self.text = self.text.trim_start();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_spaces();
+        match self.text.strip_prefix(c) {
+            Some(rest) => {
+                self.text = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{c}' at '{}'", self.text))
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<u16, String> {
+        let Some(ops) = PRECEDENCE.get(level) else {
+            return self.atom();
+        };
+
+        let mut value = self.binary(level + 1)?;
+        loop {
+            self.skip_spaces();
+            let Some(&op) = ops.iter().find(|op| self.text.starts_with(*op)) else {
+                return Ok(value);
+            };
+            self.text = &self.text[op.len()..];
+            let rhs = self.binary(level + 1)?;
+
+            let (a, b) = (u32::from(value), u32::from(rhs));
+            let modulus = u32::from(MODULUS);
+            value = match op {
+                "==" => u32::from(a == b),
+                "!=" => u32::from(a != b),
+                "<" => u32::from(a < b),
+                ">" => u32::from(a > b),
+                "+" => (a + b) % modulus,
+                "-" => (a + modulus - b) % modulus,
+                "*" => (a * b) % modulus,
+                "%" if b == 0 => return Err("mod by zero".to_string()),
+                "%" => a % b,
+                "&" => a & b,
+                _ => a | b,
+            } as u16;
+        }
+    }
+
+    fn atom(&mut self) -> Result<u16, String> {
+        self.skip_spaces();
+
+        if self.eat('(') {
+            let value = self.binary(0)?;
+            self.expect(')')?;
+            return Ok(value);
+        }
+
+        let end = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.text.len());
+        let (token, rest) = self.text.split_at(end);
+        self.text = rest;
+
+        if token.is_empty() {
+            return Err(format!("expected a value at '{rest}'"));
+        }
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return match token.parse::<u16>() {
+                Ok(value) if value < MODULUS => Ok(value),
+                _ => Err(format!("invalid literal: {token}")),
+            };
+        }
+        if token == "mem" {
+            self.expect('[')?;
+            let addr = self.binary(0)?;
+            self.expect(']')?;
+            return self
+                .vm
+                .memory
+                .get(addr as usize)
+                .copied()
+                .ok_or(format!("address {addr} is out of bounds"));
+        }
+
+        if let Some(reg) = token
+            .strip_prefix('r')
+            .and_then(|reg| reg.parse::<usize>().ok())
+        {
+            return match self.vm.registers.get(reg) {
+                Some(&value) => Ok(value),
+                None => Err(format!("invalid register: {token}")),
+            };
+        }
+        match self
+            .vm
+            .register_aliases
+            .iter()
+            .position(|alias| alias.as_deref() == Some(token))
+        {
+            Some(reg) => Ok(self.vm.registers[reg]),
+            None => Err(format!("unknown register alias: {token}")),
+        }
+    }
+}
+
+impl VM {
+    /// Evaluates an expression over the current registers and memory, such
+    /// as `r0+1` or `mem[r2+3] & 255`. Operands are `r0`..`r7` or their
+    /// `set_register_alias` names, decimal literals below 32768, `mem[EXPR]`
+    /// and parentheses; the operators `+ - * % & |` wrap modulo 32768 like
+    /// the VM's own instructions, and `== != < >` give 1 or 0. `*` and `%`
+    /// bind tightest, comparisons loosest.
+    pub fn eval_expr(&self, expr: &str) -> Result<u16, String> {
+        let mut evaluator = Evaluator {
+            vm: self,
+            text: expr,
+        };
+        let value = evaluator.binary(0)?;
+        evaluator.skip_spaces();
+        if !evaluator.text.is_empty() {
+            return Err(format!("unexpected '{}'", evaluator.text));
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,32 @@
+//! A SIGINT handler behind the `interrupt` feature, so Ctrl-C stops a run
+//! cleanly instead of killing the process.
+
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+const SIGINT: c_int = 2;
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+/// The flag the handler raises. Only set once, before the handler is.
+static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+extern "C" fn on_sigint(_: c_int) {
+    if let Some(flag) = FLAG.get() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Routes SIGINT to a flag for `VM::set_interrupt_flag` instead of the
+/// default of killing the process. Installing it again returns the same flag.
+pub fn install_interrupt_handler() -> Arc<AtomicBool> {
+    let flag = Arc::clone(FLAG.get_or_init(Arc::default));
+    // SAFETY: the handler only does an atomic load and store.
+    unsafe {
+        signal(SIGINT, on_sigint);
+    }
+    flag
+}
@@ -0,0 +1,2371 @@
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::ops::Range;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod analysis;
+mod asm;
+mod builder;
+pub mod codes;
+mod decompile;
+mod disasm;
+mod error;
+mod expr;
+#[cfg(all(feature = "interrupt", unix))]
+mod interrupt;
+mod observer;
+pub mod puzzles;
+mod state;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use analysis::{find_refs, ControlFlowGraph, RefTarget};
+pub use asm::assemble;
+pub use builder::VmBuilder;
+pub use decompile::decompile_routine;
+use disasm::{format_arg, format_instruction, format_trace_json};
+pub use error::VmError;
+#[cfg(all(feature = "interrupt", unix))]
+pub use interrupt::install_interrupt_handler;
+pub use observer::VmObserver;
+pub use state::{diff_states, StateDiff, VmState};
+#[cfg(feature = "wasm")]
+pub use wasm::{
+    WasmVm, STATUS_ERROR, STATUS_HALTED, STATUS_NEEDS_INPUT, STATUS_RUNNING, STATUS_STOPPED,
+};
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Halt,
+    Set,
+    Push,
+    Pop,
+    Eq,
+    Gt,
+    Jmp,
+    Jt,
+    Jf,
+    Add,
+    Mult,
+    Mod,
+    And,
+    Or,
+    Not,
+    Rmem,
+    Wmem,
+    Call,
+    Ret,
+    Out,
+    In,
+    Noop,
+}
+
+#[derive(Debug)]
+pub struct OpSpec {
+    pub code: u16,
+    pub op: Op,
+    pub mnemonic: &'static str,
+    pub arity: usize,
+}
+
+/// The instruction set, indexed by opcode. Both decoding and disassembly are
+/// driven from this table.
+pub const OPCODES: [OpSpec; 22] = [
+    OpSpec {
+        code: 0,
+        op: Op::Halt,
+        mnemonic: "halt",
+        arity: 0,
+    },
+    OpSpec {
+        code: 1,
+        op: Op::Set,
+        mnemonic: "set",
+        arity: 2,
+    },
+    OpSpec {
+        code: 2,
+        op: Op::Push,
+        mnemonic: "push",
+        arity: 1,
+    },
+    OpSpec {
+        code: 3,
+        op: Op::Pop,
+        mnemonic: "pop",
+        arity: 1,
+    },
+    OpSpec {
+        code: 4,
+        op: Op::Eq,
+        mnemonic: "eq",
+        arity: 3,
+    },
+    OpSpec {
+        code: 5,
+        op: Op::Gt,
+        mnemonic: "gt",
+        arity: 3,
+    },
+    OpSpec {
+        code: 6,
+        op: Op::Jmp,
+        mnemonic: "jmp",
+        arity: 1,
+    },
+    OpSpec {
+        code: 7,
+        op: Op::Jt,
+        mnemonic: "jt",
+        arity: 2,
+    },
+    OpSpec {
+        code: 8,
+        op: Op::Jf,
+        mnemonic: "jf",
+        arity: 2,
+    },
+    OpSpec {
+        code: 9,
+        op: Op::Add,
+        mnemonic: "add",
+        arity: 3,
+    },
+    OpSpec {
+        code: 10,
+        op: Op::Mult,
+        mnemonic: "mult",
+        arity: 3,
+    },
+    OpSpec {
+        code: 11,
+        op: Op::Mod,
+        mnemonic: "mod",
+        arity: 3,
+    },
+    OpSpec {
+        code: 12,
+        op: Op::And,
+        mnemonic: "and",
+        arity: 3,
+    },
+    OpSpec {
+        code: 13,
+        op: Op::Or,
+        mnemonic: "or",
+        arity: 3,
+    },
+    OpSpec {
+        code: 14,
+        op: Op::Not,
+        mnemonic: "not",
+        arity: 2,
+    },
+    OpSpec {
+        code: 15,
+        op: Op::Rmem,
+        mnemonic: "rmem",
+        arity: 2,
+    },
+    OpSpec {
+        code: 16,
+        op: Op::Wmem,
+        mnemonic: "wmem",
+        arity: 2,
+    },
+    OpSpec {
+        code: 17,
+        op: Op::Call,
+        mnemonic: "call",
+        arity: 1,
+    },
+    OpSpec {
+        code: 18,
+        op: Op::Ret,
+        mnemonic: "ret",
+        arity: 0,
+    },
+    OpSpec {
+        code: 19,
+        op: Op::Out,
+        mnemonic: "out",
+        arity: 1,
+    },
+    OpSpec {
+        code: 20,
+        op: Op::In,
+        mnemonic: "in",
+        arity: 1,
+    },
+    OpSpec {
+        code: 21,
+        op: Op::Noop,
+        mnemonic: "noop",
+        arity: 0,
+    },
+];
+
+impl Op {
+    pub fn spec(self) -> &'static OpSpec {
+        &OPCODES[self as usize]
+    }
+
+    /// Whether the first operand is a register the instruction writes.
+    pub fn writes_register(self) -> bool {
+        matches!(
+            self,
+            Op::Set
+                | Op::Pop
+                | Op::Eq
+                | Op::Gt
+                | Op::Add
+                | Op::Mult
+                | Op::Mod
+                | Op::And
+                | Op::Or
+                | Op::Not
+                | Op::Rmem
+                | Op::In
+        )
+    }
+}
+
+impl TryFrom<u16> for Op {
+    type Error = VmError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        OPCODES
+            .get(value as usize)
+            .filter(|spec| spec.code == value)
+            .map(|spec| spec.op)
+            .ok_or(VmError::UnknownOpcode(value))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Arg {
+    Literal(u16),
+    Register(usize),
+}
+
+impl TryFrom<u16> for Arg {
+    type Error = VmError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0..=32767 => Ok(Self::Literal(value)),
+            32768 => Ok(Self::Register(0)),
+            32769 => Ok(Self::Register(1)),
+            32770 => Ok(Self::Register(2)),
+            32771 => Ok(Self::Register(3)),
+            32772 => Ok(Self::Register(4)),
+            32773 => Ok(Self::Register(5)),
+            32774 => Ok(Self::Register(6)),
+            32775 => Ok(Self::Register(7)),
+            _ => Err(VmError::InvalidArgument(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub op: Op,
+    pub a: Option<Arg>,
+    pub b: Option<Arg>,
+    pub c: Option<Arg>,
+}
+
+impl Instruction {
+    pub fn new(op: Op) -> Self {
+        Self {
+            op,
+            a: None,
+            b: None,
+            c: None,
+        }
+    }
+
+    /// Decodes the instruction starting at `addr` in `memory`, returning it
+    /// along with the address of the following instruction.
+    pub fn decode(memory: &[u16], addr: usize) -> Result<(Self, usize), VmError> {
+        let op: Op = memory
+            .get(addr)
+            .copied()
+            .ok_or(VmError::OutOfBounds { addr, op: None })?
+            .try_into()?;
+        let mut inst = Self::new(op);
+
+        let mut next = addr + 1;
+        for arg in [&mut inst.a, &mut inst.b, &mut inst.c]
+            .into_iter()
+            .take(op.spec().arity)
+        {
+            let word = *memory.get(next).ok_or(VmError::OutOfBounds {
+                addr: next,
+                op: Some(op),
+            })?;
+            *arg = Some(word.try_into()?);
+            next += 1;
+        }
+
+        Ok((inst, next))
+    }
+
+    /// Checks the operand kinds `decode` doesn't: an instruction that writes
+    /// a register must name one as its destination.
+    pub fn validate(&self) -> Result<(), VmError> {
+        match self.a {
+            Some(Arg::Literal(_)) if self.op.writes_register() => Err(VmError::ExpectedRegister {
+                op: self.op,
+                operand: 0,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// How many words the instruction occupies: the opcode plus one per
+    /// operand.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        1 + [self.a, self.b, self.c].iter().flatten().count()
+    }
+}
+
+pub const MEMORY_SIZE: usize = 0b1000_0000_0000_0000;
+
+/// All arithmetic is done modulo this value, keeping results in 15 bits.
+pub const MODULUS: u16 = 32768;
+
+/// Names given to registers with `set_register_alias`, by register.
+pub(crate) type RegisterAliases = [Option<String>; 8];
+
+/// The longest instruction: an opcode and three operands.
+const MAX_INSTRUCTION_WORDS: usize = 4;
+
+/// The default for `set_max_stack_depth`, far beyond anything the challenge
+/// needs but small enough to stop runaway recursion long before memory runs
+/// out.
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 1 << 20;
+
+/// How many instructions run between wall-clock checks against the time limit.
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+/// How many `halt`s soft-halt mode steps over before really stopping, so a
+/// run into zeroed memory still terminates.
+const MAX_SOFT_HALTS: u32 = 64;
+
+/// How much recent output is kept around for matching prompts.
+const OUTPUT_TAIL_SIZE: usize = 256;
+
+/// An in-memory writer whose clones share one buffer, so output handed to the
+/// VM as a `Box<dyn Write>` can still be read back afterwards.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.borrow().clone()
+    }
+
+    /// Returns everything written so far and empties the buffer.
+    pub fn take(&self) -> Vec<u8> {
+        std::mem::take(&mut *self.0.borrow_mut())
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Copies everything that passes through `inner` to `copy` as well, flushing
+/// `copy` after each newline so it stays current during a long session. As a
+/// writer it writes to both; as a reader it reads from `inner` and copies
+/// what was read.
+pub struct Tee<A, B> {
+    inner: A,
+    copy: B,
+}
+
+impl<A, B: Write> Tee<A, B> {
+    pub fn new(inner: A, copy: B) -> Self {
+        Self { inner, copy }
+    }
+
+    fn copy(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.copy.write_all(buf)?;
+        if buf.contains(&b'\n') {
+            self.copy.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.copy(&buf[..written])?;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()?;
+        self.copy.flush()
+    }
+}
+
+impl<A: Read, B: Write> Read for Tee<A, B> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.copy(&buf[..read])?;
+        Ok(read)
+    }
+}
+
+impl<A: BufRead, B: Write> BufRead for Tee<A, B> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amount: usize) {
+        // `consume` can't fail, so a failed copy is dropped rather than lost
+        // input.
+        if let Ok(buf) = self.inner.fill_buf() {
+            let consumed = buf[..amount.min(buf.len())].to_vec();
+            let _ = self.copy(&consumed);
+        }
+        self.inner.consume(amount);
+    }
+}
+
+/// Somewhere `Op::In` can pull lines from. The VM walks its sources in order,
+/// moving on to the next only once the current one is exhausted.
+enum InputSource {
+    Queue(Vec<u8>),
+    Script(Box<dyn BufRead>),
+    /// The interactive input, stdin unless the VM was built `with_input`.
+    Input(Box<dyn BufRead>),
+}
+
+impl InputSource {
+    /// Appends the next line (including its newline, if any) to `line` and
+    /// returns how many bytes were read; zero means the source is exhausted.
+    /// With `raw`, readers give up a single byte instead of a line.
+    fn read_line(&mut self, line: &mut Vec<u8>, raw: bool) -> Result<usize, VmError> {
+        match self {
+            Self::Script(reader) | Self::Input(reader) if raw => {
+                let Some(&byte) = reader.fill_buf()?.first() else {
+                    return Ok(0);
+                };
+                reader.consume(1);
+                line.push(byte);
+                Ok(1)
+            }
+            Self::Queue(text) => {
+                let end = text
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(text.len(), |i| i + 1);
+                line.extend(text.drain(..end));
+                Ok(end)
+            }
+            Self::Script(reader) | Self::Input(reader) => Ok(reader.read_until(b'\n', line)?),
+        }
+    }
+}
+
+/// What `in` does with a line that holds nothing but its newline. Running out
+/// of input altogether always ends the run with `HaltReason::OutOfInput`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BlankLinePolicy {
+    /// Hand the program the lone `\n`, like any other command.
+    #[default]
+    Deliver,
+    /// Treat the blank line as the end of input, as if the source ran dry.
+    EndInput,
+}
+
+/// What `step` does on reaching a word that isn't an opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnknownOpPolicy {
+    /// Fail with `VmError::UnknownOpcode`.
+    #[default]
+    Abort,
+    /// Treat the word as data and move past it without executing anything.
+    SkipWord,
+    /// Execute the word as a one-word `noop`.
+    Noop,
+}
+
+/// How `load` and `load_from` pair up the bytes of a program image into
+/// 16-bit words.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ByteOrder {
+    /// Low byte first, as the challenge binary is distributed.
+    #[default]
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn word(self, bytes: [u8; 2]) -> u16 {
+        match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// How `set_trace` writes each executed instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    /// `ADDR: mnemonic operands`, then the values its register operands
+    /// hold afterwards as `rN=VALUE`.
+    #[default]
+    Text,
+    /// One JSON object a line, as
+    /// `{"pc":6,"op":"jt","args":[{"register":0},{"literal":1093}],"regs":[...]}`
+    /// with all eight registers as they are afterwards.
+    Json,
+}
+
+/// Why a call to `run` returned without an error.
+#[derive(Debug, PartialEq, Eq)]
+pub enum HaltReason {
+    /// The program executed `halt`.
+    Halted,
+    /// `ret` was executed with an empty stack.
+    EmptyRetStack,
+    /// The program asked for input after every input source ran dry; `pc` is
+    /// left on the `in` so more input can be supplied and the run resumed.
+    OutOfInput,
+    /// The program reached `in` while `stop_at_input` was set; `pc` is left
+    /// on the `in` so it runs once execution resumes.
+    AwaitingInput,
+    /// Execution ran past the last memory address.
+    ReachedMemoryEnd,
+    /// The `wmem` at `addr` overwrote `target`, which had already been
+    /// executed as part of an instruction.
+    CodeWrite { addr: usize, target: usize },
+    /// `run_until_break` reached the breakpoint at `addr`, which has not run.
+    Breakpoint { addr: usize },
+    /// `run_until_break` saw an instruction change a watched location.
+    Watchpoint {
+        location: WatchLocation,
+        old: u16,
+        new: u16,
+    },
+    /// The instruction at `addr` wrote the value set by `break_on_register`.
+    RegisterMatch { addr: usize },
+    /// The interrupt flag was raised; `pc` is on the next instruction to run.
+    Interrupted,
+}
+
+/// What happened when `step` executed an instruction.
+#[derive(Debug, PartialEq, Eq)]
+pub enum StepResult {
+    /// The instruction ran and execution can carry on.
+    Continue,
+    /// The instruction changed a watched location from `old` to `new`.
+    Watchpoint {
+        location: WatchLocation,
+        old: u16,
+        new: u16,
+    },
+    /// The instruction was a `wmem` into `addr`, which has already executed
+    /// as an opcode. Only reported after `set_report_self_modify`.
+    SelfModify { addr: usize, old: u16, new: u16 },
+    /// Execution stopped, for the reason `run` would have returned.
+    Stopped(HaltReason),
+}
+
+/// A memory cell or register being watched for changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchLocation {
+    Memory(usize),
+    Register(usize),
+}
+
+/// A single bit flip applied to memory, either right away or once the VM has
+/// executed `cycle` instructions.
+#[derive(Debug)]
+pub struct Fault {
+    pub addr: usize,
+    pub bit: u8,
+    pub cycle: Option<u64>,
+}
+
+/// The state `step_back` restores: everything an instruction can change
+/// apart from memory, plus the one word a `wmem` overwrote.
+struct Snapshot {
+    registers: [u16; 8],
+    stack: Vec<u16>,
+    call_frames: Vec<(usize, usize)>,
+    pc: usize,
+    cycles: u64,
+    overwritten: Option<(usize, u16)>,
+}
+
+pub struct VM {
+    memory: [u16; MEMORY_SIZE],
+    registers: [u16; 8],
+    register_aliases: RegisterAliases,
+    stack: Vec<u16>,
+    max_stack_depth: usize,
+    byte_order: ByteOrder,
+    unknown_op_policy: UnknownOpPolicy,
+    pc: usize,
+    output: Box<dyn Write>,
+    flush_on_newline: bool,
+    echo_input: bool,
+    mmio_output: Range<usize>,
+    mmio_input: Range<usize>,
+    current_pc: usize,
+    cycles: u64,
+    pending_fault: Option<Fault>,
+    io_log: Option<Box<dyn Write>>,
+    prompt_responses: Vec<(String, String)>,
+    output_tail: String,
+    time_limit: Option<Duration>,
+    max_cycles: Option<u64>,
+    register_break: Option<(usize, u16)>,
+    register_break_hit: Option<usize>,
+    reg_csv: Option<Box<dyn Write>>,
+    trace: Option<Box<dyn Write>>,
+    trace_format: TraceFormat,
+    on_output: Option<Box<dyn FnMut(u8)>>,
+    observers: Vec<Box<dyn VmObserver>>,
+    soft_halt: bool,
+    soft_halts: u32,
+    /// The entry and return address of every `call` not yet returned from.
+    call_frames: Vec<(usize, usize)>,
+    call_log: Option<Box<dyn Write>>,
+    depth_profile: Option<(Box<dyn Write>, u64)>,
+    input_sources: Vec<InputSource>,
+    input_line: Vec<u8>,
+    input_pos: usize,
+    blank_line_policy: BlankLinePolicy,
+    normalize_newlines: bool,
+    raw_input: bool,
+    strict_decode: bool,
+    /// Input lines to replace, without their newline, and what with.
+    macros: HashMap<String, String>,
+    interrupt: Arc<AtomicBool>,
+    /// Every byte handed to an `in`, while recording.
+    recorded_input: Option<Vec<u8>>,
+    initialized: Vec<bool>,
+    warn_uninit: bool,
+    uninit_warned: HashSet<usize>,
+    stop_at_input: bool,
+    snapshot_at_input: Option<PathBuf>,
+    /// Every address covered by an executed instruction, operands included.
+    code: Vec<bool>,
+    /// The opcode address of every executed instruction.
+    executed: Vec<bool>,
+    hot_counts: Option<HashMap<usize, u64>>,
+    /// Executions per opcode, indexed by `Op` discriminant.
+    op_counts: Option<[u64; OPCODES.len()]>,
+    /// Decoded instructions by address, when the decode cache is on.
+    decoded: Option<Vec<Option<(Instruction, usize)>>>,
+    break_on_code_write: bool,
+    /// Breakpoints by address, with the condition that must hold for them
+    /// to stop, if any.
+    breakpoints: HashMap<usize, Option<String>>,
+    mem_watches: HashSet<usize>,
+    reg_watches: HashSet<usize>,
+    call_results: HashMap<usize, u16>,
+    watch_hit: Option<(WatchLocation, u16, u16)>,
+    report_self_modify: bool,
+    self_modify_hit: Option<(usize, u16, u16)>,
+    rewind_depth: usize,
+    history: VecDeque<Snapshot>,
+    loop_window: usize,
+    /// Hashes of the last `loop_window` (pc, registers, stack) states, with
+    /// how often each occurs among them.
+    recent_states: VecDeque<u64>,
+    recent_counts: HashMap<u64, usize>,
+}
+
+impl Default for VM {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VM {
+    /// Creates a VM that reads from stdin and writes to stdout.
+    pub fn new() -> Self {
+        Self {
+            memory: [0; MEMORY_SIZE],
+            registers: [0; 8],
+            register_aliases: Default::default(),
+            stack: Vec::new(),
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            byte_order: ByteOrder::default(),
+            unknown_op_policy: UnknownOpPolicy::default(),
+            pc: 0,
+            output: Box::new(std::io::stdout()),
+            flush_on_newline: true,
+            echo_input: false,
+            mmio_output: 0..0,
+            mmio_input: 0..0,
+            current_pc: 0,
+            cycles: 0,
+            pending_fault: None,
+            io_log: None,
+            prompt_responses: Vec::new(),
+            output_tail: String::new(),
+            time_limit: None,
+            max_cycles: None,
+            register_break: None,
+            register_break_hit: None,
+            reg_csv: None,
+            trace: None,
+            trace_format: TraceFormat::default(),
+            on_output: None,
+            observers: Vec::new(),
+            soft_halt: false,
+            soft_halts: 0,
+            call_frames: Vec::new(),
+            call_log: None,
+            depth_profile: None,
+            input_sources: vec![InputSource::Input(Box::new(BufReader::new(
+                std::io::stdin(),
+            )))],
+            input_line: Vec::new(),
+            input_pos: 0,
+            blank_line_policy: BlankLinePolicy::default(),
+            normalize_newlines: true,
+            raw_input: false,
+            strict_decode: false,
+            macros: HashMap::new(),
+            interrupt: Arc::default(),
+            recorded_input: None,
+            initialized: vec![false; MEMORY_SIZE],
+            warn_uninit: false,
+            uninit_warned: HashSet::new(),
+            stop_at_input: false,
+            snapshot_at_input: None,
+            code: vec![false; MEMORY_SIZE],
+            executed: vec![false; MEMORY_SIZE],
+            hot_counts: None,
+            op_counts: None,
+            decoded: None,
+            break_on_code_write: false,
+            breakpoints: HashMap::new(),
+            mem_watches: HashSet::new(),
+            reg_watches: HashSet::new(),
+            call_results: HashMap::new(),
+            watch_hit: None,
+            report_self_modify: false,
+            self_modify_hit: None,
+            rewind_depth: 0,
+            history: VecDeque::new(),
+            loop_window: 0,
+            recent_states: VecDeque::new(),
+            recent_counts: HashMap::new(),
+        }
+    }
+
+    pub fn builder() -> VmBuilder {
+        VmBuilder::new()
+    }
+
+    /// Creates a VM whose `in` instructions read lines from `input` instead of
+    /// stdin.
+    pub fn with_input(input: Box<dyn BufRead>) -> Self {
+        Self {
+            input_sources: vec![InputSource::Input(input)],
+            ..Self::new()
+        }
+    }
+
+    /// Creates a VM that reads exactly the bytes of `recorded`, typically
+    /// the `consumed_input` of an earlier run, and never falls back to stdin.
+    pub fn replay(recorded: &[u8]) -> Self {
+        Self {
+            input_sources: vec![InputSource::Queue(recorded.to_vec())],
+            ..Self::new()
+        }
+    }
+
+    /// Creates a VM whose `out` instructions write to `output` instead of
+    /// stdout.
+    pub fn with_output(output: Box<dyn Write>) -> Self {
+        Self {
+            output,
+            ..Self::new()
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr, None);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Adds a breakpoint at `addr` that only stops when `condition`, an
+    /// `eval_expr` expression such as `r0==5`, is non-zero there. Returns the
+    /// error if `condition` doesn't evaluate now; if it fails to evaluate at
+    /// the breakpoint later, the breakpoint stops.
+    pub fn add_conditional_breakpoint(
+        &mut self,
+        addr: usize,
+        condition: &str,
+    ) -> Result<(), String> {
+        self.eval_expr(condition)?;
+        self.breakpoints.insert(addr, Some(condition.to_string()));
+        Ok(())
+    }
+
+    /// Whether a breakpoint at the current `pc` should stop the run.
+    fn breakpoint_hit(&self) -> bool {
+        match self.breakpoints.get(&self.pc) {
+            None => false,
+            Some(None) => true,
+            Some(Some(condition)) => self.eval_expr(condition) != Ok(0),
+        }
+    }
+
+    /// Makes `step` report `StepResult::Watchpoint` whenever an instruction
+    /// changes the memory cell at `addr`.
+    pub fn add_mem_watch(&mut self, addr: usize) {
+        self.mem_watches.insert(addr);
+    }
+
+    /// Makes `step` report `StepResult::Watchpoint` whenever an instruction
+    /// changes register `reg`.
+    pub fn add_reg_watch(&mut self, reg: usize) {
+        self.reg_watches.insert(reg);
+    }
+
+    /// Makes `run` return `HaltReason::CodeWrite` after any `wmem` into memory
+    /// that has already been executed as an instruction.
+    pub fn set_break_on_code_write(&mut self, break_on_code_write: bool) {
+        self.break_on_code_write = break_on_code_write;
+    }
+
+    /// Makes `step` report `StepResult::SelfModify` for any `wmem` into an
+    /// address that has executed as an opcode; `run` prints a warning.
+    pub fn set_report_self_modify(&mut self, report_self_modify: bool) {
+        self.report_self_modify = report_self_modify;
+    }
+
+    /// Makes `run` return `HaltReason::AwaitingInput` instead of reading when
+    /// the program reaches `in`.
+    pub fn set_stop_at_input(&mut self, stop_at_input: bool) {
+        self.stop_at_input = stop_at_input;
+    }
+
+    /// Saves the state to `path`, as `save_state` does, the first time the
+    /// program reaches `in`, with `pc` on the `in` so loading it resumes at
+    /// the first prompt. Only that first `in` writes it.
+    pub fn set_snapshot_at_input(&mut self, path: PathBuf) {
+        self.snapshot_at_input = Some(path);
+    }
+
+    /// Warns on stderr, once per address, when code is fetched from or `rmem`
+    /// reads memory that was neither loaded nor written by `wmem`.
+    pub fn set_warn_uninit(&mut self, warn_uninit: bool) {
+        self.warn_uninit = warn_uninit;
+    }
+
+    fn check_initialized(&mut self, addrs: Range<usize>) {
+        if !self.warn_uninit {
+            return;
+        }
+        for addr in addrs {
+            if !self.initialized[addr] && self.uninit_warned.insert(addr) {
+                eprintln!(
+                    "warning: read of uninitialized memory at {addr} (pc {})",
+                    self.current_pc
+                );
+            }
+        }
+    }
+
+    /// Queues `text` to be read before any script or stdin input.
+    pub fn queue_input(&mut self, text: &str) {
+        self.queue_input_bytes(text.as_bytes());
+    }
+
+    /// Like `queue_input`, for input that needn't be UTF-8.
+    pub fn queue_input_bytes(&mut self, bytes: &[u8]) {
+        match self.input_sources.first_mut() {
+            Some(InputSource::Queue(queued)) => queued.extend_from_slice(bytes),
+            _ => self
+                .input_sources
+                .insert(0, InputSource::Queue(bytes.to_vec())),
+        }
+    }
+
+    /// Reads input from `script` once queued input runs out, before stdin.
+    pub fn add_input_script(&mut self, script: Box<dyn BufRead>) {
+        let index = self
+            .input_sources
+            .iter()
+            .position(|source| matches!(source, InputSource::Input(_)))
+            .unwrap_or(self.input_sources.len());
+        self.input_sources
+            .insert(index, InputSource::Script(script));
+    }
+
+    /// Records every byte delivered to an `in` from now on, for
+    /// `consumed_input`. Turning recording off discards what was recorded.
+    pub fn set_record_input(&mut self, record: bool) {
+        if !record {
+            self.recorded_input = None;
+        } else if self.recorded_input.is_none() {
+            self.recorded_input = Some(Vec::new());
+        }
+    }
+
+    /// The bytes `in` has consumed since `set_record_input`, in order.
+    pub fn consumed_input(&self) -> &[u8] {
+        self.recorded_input.as_deref().unwrap_or_default()
+    }
+
+    pub fn set_blank_line_policy(&mut self, policy: BlankLinePolicy) {
+        self.blank_line_policy = policy;
+    }
+
+    /// The flag `run` and `run_until_break` check between instructions;
+    /// raising it from another thread or a signal handler stops the run with
+    /// `HaltReason::Interrupted`, and the flag is lowered again.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    /// Replaces the interrupt flag, e.g. with the one from
+    /// `install_interrupt_handler`.
+    pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.interrupt = flag;
+    }
+
+    /// Replaces any input line that reads exactly `name` with `expansion`
+    /// before `in` sees it, so `n` can stand for `go north`. The expansion
+    /// becomes one line, with a newline added if it has none. Doesn't apply
+    /// to raw input, which never arrives a line at a time.
+    pub fn add_macro(&mut self, name: &str, expansion: &str) {
+        self.macros.insert(name.to_string(), expansion.to_string());
+    }
+
+    /// Pulls input from scripts and the interactive reader one byte at a
+    /// time instead of a line at a time, so `in` doesn't wait for a newline
+    /// the program may never need. A terminal still buffers stdin by line
+    /// unless it is switched to raw mode itself.
+    pub fn set_raw_input(&mut self, raw: bool) {
+        self.raw_input = raw;
+    }
+
+    /// Drops every `\r` from input lines before `in` sees them, so `\r\n`
+    /// line endings reach the program as `\n`. On by default; doesn't apply
+    /// to prompt responses, which never contain one.
+    pub fn set_normalize_newlines(&mut self, normalize: bool) {
+        self.normalize_newlines = normalize;
+    }
+
+    /// Returns the next byte of input, refilling the line buffer from a
+    /// matching prompt response or the input sources once it is used up.
+    /// Multi-byte characters arrive as several `in`s.
+    fn next_input_byte(&mut self) -> Result<Option<u8>, VmError> {
+        if self.input_pos >= self.input_line.len() {
+            let mut line = Vec::new();
+            if let Some(response) = self.prompt_response() {
+                line.extend_from_slice(response.as_bytes());
+                line.push(b'\n');
+                self.output_tail.clear();
+            } else {
+                self.read_input_line(&mut line)?;
+            }
+            self.input_line = line;
+            self.input_pos = 0;
+        }
+
+        let byte = self.input_line.get(self.input_pos).copied();
+        if byte.is_some() {
+            self.input_pos += 1;
+        }
+        Ok(byte)
+    }
+
+    fn read_input_line(&mut self, line: &mut Vec<u8>) -> Result<(), VmError> {
+        loop {
+            while let Some(source) = self.input_sources.first_mut() {
+                if source.read_line(line, self.raw_input)? > 0 {
+                    break;
+                }
+                self.input_sources.remove(0);
+            }
+            if self.normalize_newlines {
+                line.retain(|&b| b != b'\r');
+            }
+            // A raw read that only got a dropped `\r` has to try again.
+            if !line.is_empty() || self.input_sources.is_empty() {
+                break;
+            }
+        }
+
+        let text = line.strip_suffix(b"\n").unwrap_or(line);
+        if let Some(expansion) = std::str::from_utf8(text)
+            .ok()
+            .filter(|_| !self.raw_input)
+            .and_then(|text| self.macros.get(text))
+        {
+            line.clear();
+            line.extend_from_slice(expansion.as_bytes());
+            if !line.ends_with(b"\n") {
+                line.push(b'\n');
+            }
+        }
+
+        let blank =
+            !self.raw_input && !line.is_empty() && line.iter().all(|&b| b == b'\r' || b == b'\n');
+        if blank && self.blank_line_policy == BlankLinePolicy::EndInput {
+            line.clear();
+        }
+
+        Ok(())
+    }
+
+    /// Logs `enter ADDR` on every `call` and `exit ADDR` on the matching `ret`,
+    /// indented by call depth.
+    pub fn set_call_log(&mut self, log: Box<dyn Write>) {
+        self.call_log = Some(log);
+    }
+
+    fn log_call(&mut self, event: &str, entry: usize) -> Result<(), VmError> {
+        if let Some(log) = &mut self.call_log {
+            let indent = "  ".repeat(self.call_frames.len());
+            writeln!(log, "{indent}{event} {entry}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes a `cycle,depth` sample of the current call depth every
+    /// `interval` instructions, for plotting recursion over time.
+    pub fn set_depth_profile(
+        &mut self,
+        mut samples: Box<dyn Write>,
+        interval: u64,
+    ) -> Result<(), VmError> {
+        writeln!(samples, "cycle,depth")?;
+        self.depth_profile = Some((samples, interval.max(1)));
+        Ok(())
+    }
+
+    /// Treats `halt` as a separator between stages and keeps executing after
+    /// it, up to `MAX_SOFT_HALTS` times.
+    pub fn set_soft_halt(&mut self, soft_halt: bool) {
+        self.soft_halt = soft_halt;
+    }
+
+    /// Writes a CSV row with the cycle count and all eight registers after
+    /// every executed instruction.
+    pub fn set_reg_csv(&mut self, mut csv: Box<dyn Write>) -> Result<(), VmError> {
+        writeln!(csv, "cycle,r0,r1,r2,r3,r4,r5,r6,r7")?;
+        self.reg_csv = Some(csv);
+        Ok(())
+    }
+
+    /// Overwrites register `reg`, e.g. to preset r7 before running.
+    pub fn set_register(&mut self, reg: usize, value: u16) -> Result<(), VmError> {
+        let slot = self
+            .registers
+            .get_mut(reg)
+            .ok_or(VmError::BadRegister(reg))?;
+        *slot = value;
+        Ok(())
+    }
+
+    /// Names register `reg` `name` in disassembly, traces and `eval_expr`,
+    /// which still accepts `rN` too. The name must be an identifier other
+    /// than `mem` or a register's own name.
+    pub fn set_register_alias(&mut self, reg: usize, name: &str) -> Result<(), VmError> {
+        let identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        let reserved = name == "mem"
+            || name
+                .strip_prefix('r')
+                .is_some_and(|reg| reg.parse::<usize>().is_ok());
+        if !identifier || reserved {
+            return Err(VmError::InvalidRegisterAlias(name.to_string()));
+        }
+        let alias = self
+            .register_aliases
+            .get_mut(reg)
+            .ok_or(VmError::BadRegister(reg))?;
+        *alias = Some(name.to_string());
+        Ok(())
+    }
+
+    pub fn register_alias(&self, reg: usize) -> Option<&str> {
+        self.register_aliases.get(reg)?.as_deref()
+    }
+
+    /// Makes every `call` to `target` skip the routine and leave `value` in
+    /// r0 as though it had returned it.
+    pub fn set_call_result(&mut self, target: usize, value: u16) {
+        self.call_results.insert(target, value);
+    }
+
+    /// Calls `callback` with every byte `out` produces, on top of writing it
+    /// to the output.
+    pub fn set_on_output(&mut self, callback: Box<dyn FnMut(u8)>) {
+        self.on_output = Some(callback);
+    }
+
+    /// Registers `observer` to be called around every instruction, after any
+    /// observers registered before it.
+    pub fn add_observer(&mut self, observer: Box<dyn VmObserver>) {
+        self.observers.push(observer);
+    }
+
+    /// Writes a line to `trace` for every executed instruction, in the
+    /// format picked with `set_trace_format`.
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    pub fn set_trace_format(&mut self, format: TraceFormat) {
+        self.trace_format = format;
+    }
+
+    /// Stops `run` right after the instruction that writes `value` into `reg`.
+    pub fn break_on_register(&mut self, reg: usize, value: u16) {
+        self.register_break = Some((reg, value));
+    }
+
+    fn reg_read(&self, reg: usize) -> Result<u16, VmError> {
+        self.registers
+            .get(reg)
+            .copied()
+            .ok_or(VmError::BadRegister(reg))
+    }
+
+    /// Every register write goes through here so watches and the register
+    /// break see it.
+    fn reg_write(&mut self, reg: usize, value: u16) -> Result<(), VmError> {
+        let slot = self
+            .registers
+            .get_mut(reg)
+            .ok_or(VmError::BadRegister(reg))?;
+        let old = std::mem::replace(slot, value);
+        if old != value && self.reg_watches.contains(&reg) {
+            self.watch_hit = Some((WatchLocation::Register(reg), old, value));
+        }
+        if self.register_break == Some((reg, value)) {
+            self.register_break_hit = Some(self.current_pc);
+        }
+        Ok(())
+    }
+
+    /// The address of the next instruction to execute.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// The address of the instruction being executed, or last executed.
+    pub fn current_pc(&self) -> usize {
+        self.current_pc
+    }
+
+    pub fn registers(&self) -> &[u16; 8] {
+        &self.registers
+    }
+
+    pub fn memory(&self) -> &[u16] {
+        &self.memory
+    }
+
+    /// The word at `addr`, or `None` past the end of memory.
+    pub fn peek_mem(&self, addr: usize) -> Option<u16> {
+        self.memory.get(addr).copied()
+    }
+
+    /// Overwrites the word at `addr`, e.g. to patch a loaded program.
+    pub fn poke_mem(&mut self, addr: usize, value: u16) -> Result<(), VmError> {
+        let slot = self
+            .memory
+            .get_mut(addr)
+            .ok_or(VmError::OutOfBounds { addr, op: None })?;
+        *slot = value;
+        self.initialized[addr] = true;
+        self.invalidate_decoded(addr);
+        Ok(())
+    }
+
+    /// The return address of every `call` still in progress, most recent
+    /// first. Tracked alongside the stack, so data pushed between calls
+    /// doesn't show up.
+    pub fn backtrace(&self) -> Vec<usize> {
+        self.call_frames.iter().rev().map(|&(_, ret)| ret).collect()
+    }
+
+    /// The stack, bottom first.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// How many instructions have executed. The count carries over between
+    /// `run` and `step` calls until `reset_metrics` clears it.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// The opcode address of every instruction executed so far, in address
+    /// order.
+    pub fn executed_addresses(&self) -> Vec<usize> {
+        (0..self.executed.len())
+            .filter(|&addr| self.executed[addr])
+            .collect()
+    }
+
+    /// The fraction of memory addresses that have executed as an opcode.
+    pub fn coverage_ratio(&self) -> f64 {
+        let count = self.executed.iter().filter(|&&executed| executed).count();
+        count as f64 / self.executed.len() as f64
+    }
+
+    /// Starts counting how often each opcode address executes, for
+    /// `hot_addresses`.
+    pub fn set_count_addresses(&mut self, count: bool) {
+        self.hot_counts = count.then(HashMap::new);
+    }
+
+    /// Each executed opcode address with how many times it ran, busiest
+    /// first. Empty unless `set_count_addresses` is on.
+    pub fn hot_addresses(&self) -> Vec<(usize, u64)> {
+        let mut hot: Vec<(usize, u64)> = self
+            .hot_counts
+            .iter()
+            .flatten()
+            .map(|(&addr, &count)| (addr, count))
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot
+    }
+
+    /// Starts counting how often each opcode executes, for
+    /// `opcode_histogram`.
+    pub fn set_count_opcodes(&mut self, count: bool) {
+        self.op_counts = count.then_some([0; OPCODES.len()]);
+    }
+
+    /// The mnemonic of each executed opcode with how many times it ran, most
+    /// frequent first. Empty unless `set_count_opcodes` is on.
+    pub fn opcode_histogram(&self) -> Vec<(String, u64)> {
+        let mut histogram: Vec<(String, u64)> = OPCODES
+            .iter()
+            .zip(self.op_counts.iter().flatten())
+            .filter(|&(_, &count)| count > 0)
+            .map(|(spec, &count)| (spec.mnemonic.to_string(), count))
+            .collect();
+        histogram.sort_by_key(|&(_, count)| Reverse(count));
+        histogram
+    }
+
+    /// Clears the execution counters so a later `run` can be measured on its
+    /// own: the cycle count, the coverage map, and the per-address and
+    /// per-opcode counts if they are on. Memory, registers, stack and `pc`
+    /// are untouched.
+    pub fn reset_metrics(&mut self) {
+        self.cycles = 0;
+        self.code.fill(false);
+        self.executed.fill(false);
+        if let Some(counts) = &mut self.hot_counts {
+            counts.clear();
+        }
+        if let Some(counts) = &mut self.op_counts {
+            counts.fill(0);
+        }
+    }
+
+    /// Puts the VM back at the start of the loaded program: registers zeroed,
+    /// stack and call frames emptied and `pc` at 0. Rewind history and any
+    /// partly read input line are dropped too. Memory is left intact, so the
+    /// program can be run again.
+    pub fn reset(&mut self) {
+        self.registers = [0; 8];
+        self.stack.clear();
+        self.call_frames.clear();
+        self.history.clear();
+        self.input_line.clear();
+        self.input_pos = 0;
+        self.pc = 0;
+        self.soft_halts = 0;
+        self.forget_states();
+    }
+
+    /// Like `reset`, but also zeroes memory.
+    pub fn reset_all(&mut self) {
+        self.reset();
+        self.memory.fill(0);
+        self.clear_decoded();
+        self.initialized.fill(false);
+        self.code.fill(false);
+        self.executed.fill(false);
+    }
+
+    /// Aborts `run` once it has been executing for longer than `limit`.
+    pub fn set_time_limit(&mut self, limit: Duration) {
+        self.time_limit = Some(limit);
+    }
+
+    /// Aborts `run` with `VmError::CycleLimitExceeded` instead of executing
+    /// more than `limit` instructions in total.
+    pub fn set_max_cycles(&mut self, limit: u64) {
+        self.max_cycles = Some(limit);
+    }
+
+    /// Answers `response` instead of reading stdin whenever the program asks
+    /// for input right after printing `prompt`.
+    pub fn add_prompt_response(&mut self, prompt: &str, response: &str) {
+        self.prompt_responses
+            .push((prompt.to_string(), response.to_string()));
+    }
+
+    fn record_output(&mut self, ch: char) {
+        if self.prompt_responses.is_empty() {
+            return;
+        }
+        self.output_tail.push(ch);
+        if self.output_tail.len() > OUTPUT_TAIL_SIZE {
+            let excess = self.output_tail.len() - OUTPUT_TAIL_SIZE;
+            let cut = (excess..self.output_tail.len())
+                .find(|&i| self.output_tail.is_char_boundary(i))
+                .unwrap_or(self.output_tail.len());
+            self.output_tail.drain(..cut);
+        }
+    }
+
+    fn prompt_response(&self) -> Option<&str> {
+        let output = self.output_tail.trim_end();
+        self.prompt_responses
+            .iter()
+            .find(|(prompt, _)| output.ends_with(prompt.as_str()))
+            .map(|(_, response)| response.as_str())
+    }
+
+    /// Records every byte consumed by `in` and emitted by `out`, one per line,
+    /// stamped with the cycle of the instruction that moved it.
+    pub fn set_io_log(&mut self, log: Box<dyn Write>) {
+        self.io_log = Some(log);
+    }
+
+    /// Writes the low byte of `value` to the output, as `out` does. The byte
+    /// is written as-is rather than encoded as a char, so bytes above 127
+    /// pass through unchanged.
+    fn write_output(&mut self, value: u16) -> Result<(), VmError> {
+        self.log_io("out", value)?;
+        let byte = value as u8;
+        self.record_output(char::from(byte));
+        self.output.write_all(&[byte])?;
+        if byte == b'\n' && self.flush_on_newline {
+            self.output.flush()?;
+        }
+        if let Some(callback) = &mut self.on_output {
+            callback(byte);
+        }
+        Ok(())
+    }
+
+    /// Consumes the next byte of input, as `in` does, or returns `None` once
+    /// input has run out.
+    fn read_input(&mut self) -> Result<Option<u16>, VmError> {
+        let Some(byte) = self.next_input_byte()? else {
+            return Ok(None);
+        };
+        let value = u16::from(byte);
+        self.log_io("in", value)?;
+        if let Some(recorded) = &mut self.recorded_input {
+            recorded.push(byte);
+        }
+        if self.echo_input {
+            self.output.write_all(&[byte])?;
+            if byte == b'\n' && self.flush_on_newline {
+                self.output.flush()?;
+            }
+        }
+        self.forget_states();
+        Ok(Some(value))
+    }
+
+    fn log_io(&mut self, direction: &str, value: u16) -> Result<(), VmError> {
+        if let Some(log) = &mut self.io_log {
+            writeln!(log, "{} {direction} {value}", self.cycles)?;
+        }
+        Ok(())
+    }
+
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), VmError> {
+        if fault.addr >= self.memory.len() {
+            return Err(VmError::OutOfBounds {
+                addr: fault.addr,
+                op: None,
+            });
+        }
+        if fault.bit > 15 {
+            return Err(VmError::InvalidFaultBit(fault.bit));
+        }
+
+        match fault.cycle {
+            Some(_) => self.pending_fault = Some(fault),
+            None => {
+                self.memory[fault.addr] ^= 1 << fault.bit;
+                self.invalidate_decoded(fault.addr);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Flushes the output after every newline `out` writes, so a prompt is
+    /// visible before the program waits for input. On by default.
+    pub fn set_flush_on_newline(&mut self, flush: bool) {
+        self.flush_on_newline = flush;
+    }
+
+    /// Writes each byte of input the program consumes to the output too, so
+    /// a captured transcript shows the commands alongside the responses.
+    pub fn set_echo_input(&mut self, echo: bool) {
+        self.echo_input = echo;
+    }
+
+    /// Sends every `wmem` into `addrs` to the output as if by `out`, instead of
+    /// storing it. Empty, and so off, by default.
+    pub fn set_mmio_output(&mut self, addrs: Range<usize>) {
+        self.mmio_output = addrs;
+    }
+
+    /// Makes every `rmem` from `addrs` consume a byte of input as if by `in`,
+    /// instead of reading memory. Empty, and so off, by default.
+    pub fn set_mmio_input(&mut self, addrs: Range<usize>) {
+        self.mmio_input = addrs;
+    }
+
+    pub fn set_unknown_op_policy(&mut self, policy: UnknownOpPolicy) {
+        self.unknown_op_policy = policy;
+        // A cached `noop` stood in for an unknown opcode under the old policy.
+        self.clear_decoded();
+    }
+
+    /// Sets how `load` and `load_from` read words from later images.
+    pub fn set_byte_order(&mut self, order: ByteOrder) {
+        self.byte_order = order;
+    }
+
+    pub fn load(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        let words = self.words(bytes)?;
+        self.load_words(&words)
+    }
+
+    /// Like `load`, but writes the image starting at `offset` and leaves the
+    /// rest of memory as it was, so a stub or replacement routine can be laid
+    /// over a loaded program. Nothing is written unless it all fits.
+    pub fn load_at(&mut self, bytes: &[u8], offset: usize) -> Result<(), VmError> {
+        let words = self.words(bytes)?;
+        let end = offset
+            .checked_add(words.len())
+            .filter(|&end| end <= self.memory.len())
+            .ok_or(VmError::OutOfBounds {
+                addr: offset.max(self.memory.len()),
+                op: None,
+            })?;
+
+        self.memory[offset..end].copy_from_slice(&words);
+        self.clear_decoded();
+        self.initialized[offset..end].fill(true);
+
+        Ok(())
+    }
+
+    /// Splits a program image into words in the configured byte order.
+    fn words(&self, bytes: &[u8]) -> Result<Vec<u16>, VmError> {
+        bytes
+            .chunks(2)
+            .map(|slice| match slice.get(0..2) {
+                Some(&[first, second]) => Ok(self.byte_order.word([first, second])),
+                _ => Err(VmError::OddProgramLength(bytes.len())),
+            })
+            .collect()
+    }
+
+    /// Like `load`, but streams the image from `reader` straight into memory
+    /// without buffering it first. On error, memory may hold part of it.
+    pub fn load_from<R: Read>(&mut self, reader: R) -> Result<(), VmError> {
+        let mut bytes = BufReader::new(reader).bytes();
+        let mut addr = 0;
+
+        while let Some(first) = bytes.next() {
+            let first = first?;
+            let second = bytes
+                .next()
+                .ok_or(VmError::OddProgramLength(addr * 2 + 1))??;
+            if addr >= self.memory.len() {
+                return Err(VmError::ProgramTooLarge(addr + 1));
+            }
+            self.memory[addr] = self.byte_order.word([first, second]);
+            self.initialized[addr] = true;
+            addr += 1;
+        }
+        self.clear_decoded();
+
+        Ok(())
+    }
+
+    /// Copies `words` into memory starting at address 0.
+    pub fn load_words(&mut self, words: &[u16]) -> Result<(), VmError> {
+        if words.len() > self.memory.len() {
+            return Err(VmError::ProgramTooLarge(words.len()));
+        }
+
+        self.memory[..words.len()].copy_from_slice(words);
+        self.clear_decoded();
+        self.initialized[..words.len()].fill(true);
+
+        Ok(())
+    }
+
+    /// Writes 1 to register `dest` if `holds` for the values of `b` and `c`,
+    /// and 0 otherwise. Values are compared as unsigned 15-bit numbers.
+    fn compare(
+        &mut self,
+        dest: usize,
+        b: Arg,
+        c: Arg,
+        holds: fn(&u16, &u16) -> bool,
+    ) -> Result<(), VmError> {
+        let holds = holds(&self.get_value(b)?, &self.get_value(c)?);
+        self.reg_write(dest, u16::from(holds))
+    }
+
+    fn push(&mut self, value: u16) -> Result<(), VmError> {
+        if self.stack.len() >= self.max_stack_depth {
+            return Err(VmError::StackOverflow {
+                addr: self.current_pc,
+                depth: self.max_stack_depth,
+            });
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    /// Makes `push` and `call` fail with `VmError::StackOverflow` rather than
+    /// grow the stack past `depth` entries.
+    pub fn set_max_stack_depth(&mut self, depth: usize) {
+        self.max_stack_depth = depth;
+    }
+
+    /// Reads `addr` on behalf of `op`, failing rather than panicking when it
+    /// lies outside memory.
+    fn mem_read(&self, addr: usize, op: Op) -> Result<u16, VmError> {
+        self.memory
+            .get(addr)
+            .copied()
+            .ok_or(VmError::OutOfBounds { addr, op: Some(op) })
+    }
+
+    /// Writes `value` to `addr` on behalf of `op`, returning the word it
+    /// replaced.
+    fn mem_write(&mut self, addr: usize, value: u16, op: Op) -> Result<u16, VmError> {
+        let slot = self
+            .memory
+            .get_mut(addr)
+            .ok_or(VmError::OutOfBounds { addr, op: Some(op) })?;
+        let old = std::mem::replace(slot, value);
+        self.invalidate_decoded(addr);
+        Ok(old)
+    }
+
+    /// Decodes the instruction starting at `addr` without touching `pc`,
+    /// returning it along with the address of the following instruction.
+    pub fn decode_at(&self, addr: usize) -> Result<(Instruction, usize), VmError> {
+        Instruction::decode(&self.memory, addr)
+    }
+
+    fn read_instruction(&mut self) -> Result<Instruction, VmError> {
+        let cached = self.decoded.as_ref().and_then(|cache| cache[self.pc]);
+        let (inst, next) = match cached {
+            Some(decoded) => decoded,
+            None => {
+                let decoded = match self.decode_at(self.pc) {
+                    Err(VmError::UnknownOpcode(_))
+                        if self.unknown_op_policy == UnknownOpPolicy::Noop =>
+                    {
+                        (Instruction::new(Op::Noop), self.pc + 1)
+                    }
+                    decoded => decoded?,
+                };
+                if self.strict_decode {
+                    decoded.0.validate()?;
+                }
+                if let Some(cache) = &mut self.decoded {
+                    cache[self.pc] = Some(decoded);
+                }
+                decoded
+            }
+        };
+        self.pc = next;
+        Ok(inst)
+    }
+
+    /// Rejects badly shaped instructions with `Instruction::validate` as they
+    /// are decoded, rather than failing with `InvalidInstruction` once they
+    /// run.
+    pub fn set_strict_decode(&mut self, strict: bool) {
+        self.strict_decode = strict;
+        self.clear_decoded();
+    }
+
+    /// Remembers each instruction after decoding it, so executing it again
+    /// skips the decode. Any write into an instruction drops it from the
+    /// cache.
+    pub fn set_decode_cache(&mut self, cache: bool) {
+        self.decoded = cache.then(|| vec![None; MEMORY_SIZE]);
+    }
+
+    /// Drops every cached instruction that covers `addr`.
+    fn invalidate_decoded(&mut self, addr: usize) {
+        if let Some(cache) = &mut self.decoded {
+            let first = addr.saturating_sub(MAX_INSTRUCTION_WORDS - 1);
+            cache[first..=addr].fill(None);
+        }
+    }
+
+    pub(crate) fn clear_decoded(&mut self) {
+        if let Some(cache) = &mut self.decoded {
+            cache.fill(None);
+        }
+    }
+
+    #[inline(always)]
+    fn get_value(&self, arg: Arg) -> Result<u16, VmError> {
+        match arg {
+            Arg::Literal(value) => Ok(value),
+            Arg::Register(reg) => self.reg_read(reg),
+        }
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<StepResult, VmError> {
+        if self.pc >= self.memory.len() {
+            return Ok(StepResult::Stopped(HaltReason::ReachedMemoryEnd));
+        }
+        self.register_break_hit = None;
+        self.watch_hit = None;
+        self.self_modify_hit = None;
+        let mut outcome = None;
+
+        if let Some(fault) = self
+            .pending_fault
+            .take_if(|fault| fault.cycle.is_none_or(|cycle| cycle <= self.cycles))
+        {
+            self.memory[fault.addr] ^= 1 << fault.bit;
+            self.invalidate_decoded(fault.addr);
+        }
+
+        if self.loop_window > 0 {
+            self.check_for_loop()?;
+        }
+
+        if self.rewind_depth > 0 {
+            if self.history.len() == self.rewind_depth {
+                self.history.pop_front();
+            }
+            self.history.push_back(Snapshot {
+                registers: self.registers,
+                stack: self.stack.clone(),
+                call_frames: self.call_frames.clone(),
+                pc: self.pc,
+                cycles: self.cycles,
+                overwritten: None,
+            });
+        }
+
+        self.current_pc = self.pc;
+        let instruction = match self.read_instruction() {
+            Err(VmError::UnknownOpcode(_))
+                if self.unknown_op_policy == UnknownOpPolicy::SkipWord =>
+            {
+                self.pc += 1;
+                return Ok(StepResult::Continue);
+            }
+            instruction => instruction?,
+        };
+        self.cycles += 1;
+        self.check_initialized(self.current_pc..self.pc);
+        self.code[self.current_pc..self.pc].fill(true);
+        self.executed[self.current_pc] = true;
+        if let Some(counts) = &mut self.hot_counts {
+            *counts.entry(self.current_pc).or_default() += 1;
+        }
+        if let Some(counts) = &mut self.op_counts {
+            counts[instruction.op as usize] += 1;
+        }
+        let traced = self.trace.is_some().then_some(instruction);
+        if !self.observers.is_empty() {
+            let mut observers = std::mem::take(&mut self.observers);
+            for observer in &mut observers {
+                observer.before_step(self, &instruction);
+            }
+            self.observers = observers;
+        }
+        if instruction.op == Op::In {
+            if let Some(path) = self.snapshot_at_input.take() {
+                let mut state = self.state();
+                state.pc = self.current_pc;
+                std::fs::write(path, state.encode())?;
+            }
+        }
+        match instruction {
+            Instruction { op: Op::Halt, .. } => {
+                if self.soft_halt && self.soft_halts < MAX_SOFT_HALTS {
+                    self.soft_halts += 1;
+                    eprintln!("halt at {}, continuing", self.current_pc);
+                } else {
+                    outcome = Some(HaltReason::Halted)
+                }
+            }
+            Instruction { op: Op::Noop, .. } => (),
+            Instruction { op: Op::Ret, .. } => {
+                if let Some(value) = self.stack.pop() {
+                    self.pc = value as usize;
+                    // A `push X; ret` is a computed jump, not a return from
+                    // the innermost call.
+                    let frame = self.call_frames.last().copied();
+                    if let Some((entry, _)) = frame.filter(|&(_, ret)| ret == self.pc) {
+                        self.call_frames.pop();
+                        self.log_call("exit", entry)?;
+                    }
+                } else {
+                    outcome = Some(HaltReason::EmptyRetStack)
+                }
+            }
+            Instruction {
+                op: Op::Out,
+                a: Some(arg),
+                ..
+            } => {
+                let value = self.get_value(arg)?;
+                self.write_output(value)?;
+            }
+            Instruction {
+                op: Op::Jmp,
+                a: Some(arg),
+                ..
+            } => self.pc = self.get_value(arg)? as usize,
+            Instruction {
+                op: Op::Push,
+                a: Some(arg),
+                ..
+            } => self.push(self.get_value(arg)?)?,
+            Instruction {
+                op: Op::Pop,
+                a: Some(Arg::Register(a)),
+                ..
+            } => {
+                if let Some(value) = self.stack.pop() {
+                    self.reg_write(a, value)?;
+                } else {
+                    return Err(VmError::EmptyStackPop);
+                }
+            }
+            Instruction {
+                op: Op::Call,
+                a: Some(a),
+                ..
+            } => {
+                let target = self.get_value(a)? as usize;
+                if let Some(&value) = self.call_results.get(&target) {
+                    self.reg_write(0, value)?;
+                } else {
+                    self.push(self.pc as u16)?;
+                    self.log_call("enter", target)?;
+                    self.call_frames.push((target, self.pc));
+                    self.pc = target;
+                }
+            }
+            Instruction { op: Op::In, .. } if self.stop_at_input => {
+                self.unstep();
+                return Ok(StepResult::Stopped(HaltReason::AwaitingInput));
+            }
+            Instruction {
+                op: Op::In,
+                a: Some(Arg::Register(reg)),
+                ..
+            } => {
+                let Some(value) = self.read_input()? else {
+                    self.unstep();
+                    return Ok(StepResult::Stopped(HaltReason::OutOfInput));
+                };
+                self.reg_write(reg, value)?;
+            }
+            Instruction {
+                op: Op::Jt,
+                a: Some(a),
+                b: Some(b),
+                ..
+            } => {
+                if self.get_value(a)? != 0 {
+                    self.pc = self.get_value(b)? as usize
+                }
+            }
+            Instruction {
+                op: Op::Jf,
+                a: Some(a),
+                b: Some(b),
+                ..
+            } => {
+                if self.get_value(a)? == 0 {
+                    self.pc = self.get_value(b)? as usize
+                }
+            }
+            Instruction {
+                op: Op::Set,
+                a: Some(Arg::Register(reg)),
+                b: Some(b),
+                ..
+            } => self.reg_write(reg, self.get_value(b)?)?,
+            Instruction {
+                op: Op::Not,
+                a: Some(Arg::Register(reg)),
+                b: Some(b),
+                ..
+            } => self.reg_write(reg, !(!0b0111_1111_1111_1111 | self.get_value(b)?))?,
+            Instruction {
+                op: Op::Rmem,
+                a: Some(Arg::Register(reg)),
+                b: Some(b),
+                ..
+            } => {
+                let addr = self.get_value(b)? as usize;
+                let value = if self.mmio_input.contains(&addr) {
+                    let Some(value) = self.read_input()? else {
+                        self.unstep();
+                        return Ok(StepResult::Stopped(HaltReason::OutOfInput));
+                    };
+                    value
+                } else {
+                    let value = self.mem_read(addr, Op::Rmem)?;
+                    self.check_initialized(addr..addr + 1);
+                    value
+                };
+                self.reg_write(reg, value)?
+            }
+            Instruction {
+                op: Op::Wmem,
+                a: Some(a),
+                b: Some(b),
+                ..
+            } => {
+                let addr = self.get_value(a)? as usize;
+                let value = self.get_value(b)?;
+                if self.mmio_output.contains(&addr) {
+                    self.write_output(value)?;
+                } else {
+                    let old = self.mem_write(addr, value, Op::Wmem)?;
+                    self.forget_states();
+                    if self.rewind_depth > 0 {
+                        if let Some(snapshot) = self.history.back_mut() {
+                            snapshot.overwritten = Some((addr, old));
+                        }
+                    }
+                    self.initialized[addr] = true;
+                    if old != value && self.mem_watches.contains(&addr) {
+                        self.watch_hit = Some((WatchLocation::Memory(addr), old, value));
+                    }
+                    if self.report_self_modify && self.executed[addr] {
+                        self.self_modify_hit = Some((addr, old, value));
+                    }
+                    if self.break_on_code_write && self.code[addr] {
+                        outcome = Some(HaltReason::CodeWrite {
+                            addr: self.current_pc,
+                            target: addr,
+                        });
+                    }
+                }
+            }
+            Instruction {
+                op: Op::Add,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => {
+                // Registers can hold any word `rmem` reads, so widen before
+                // adding rather than risk overflowing u16.
+                let sum = self.get_value(b)? as u32 + self.get_value(c)? as u32;
+                self.reg_write(a, (sum % MODULUS as u32) as u16)?
+            }
+            Instruction {
+                op: Op::Eq,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => self.compare(a, b, c, u16::eq)?,
+            Instruction {
+                op: Op::Gt,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => self.compare(a, b, c, u16::gt)?,
+            Instruction {
+                op: Op::And,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => self.reg_write(a, self.get_value(b)? & self.get_value(c)?)?,
+            Instruction {
+                op: Op::Or,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => self.reg_write(a, self.get_value(b)? | self.get_value(c)?)?,
+            Instruction {
+                op: Op::Mult,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => {
+                let value =
+                    (self.get_value(b)? as u32 * self.get_value(c)? as u32) as u16 % MODULUS;
+                self.reg_write(a, value)?
+            }
+            Instruction {
+                op: Op::Mod,
+                a: Some(Arg::Register(a)),
+                b: Some(b),
+                c: Some(c),
+            } => {
+                let value_b = self.get_value(b)?;
+                let value_c = self.get_value(c)?;
+                if value_c == 0 {
+                    return Err(VmError::ModByZero {
+                        addr: self.current_pc,
+                        dividend: value_b,
+                    });
+                }
+                self.reg_write(a, value_b % value_c)?
+            }
+            _ => {
+                return Err(VmError::InvalidInstruction {
+                    addr: self.current_pc,
+                    instruction,
+                })
+            }
+        }
+
+        if let Some(inst) = traced {
+            self.write_trace(&inst)?;
+        }
+
+        if !self.observers.is_empty() {
+            let mut observers = std::mem::take(&mut self.observers);
+            for observer in &mut observers {
+                observer.after_step(self);
+            }
+            self.observers = observers;
+        }
+
+        if let Some(csv) = &mut self.reg_csv {
+            let [r0, r1, r2, r3, r4, r5, r6, r7] = self.registers;
+            writeln!(
+                csv,
+                "{},{r0},{r1},{r2},{r3},{r4},{r5},{r6},{r7}",
+                self.cycles
+            )?;
+        }
+
+        if let Some((samples, interval)) = &mut self.depth_profile {
+            if self.cycles.is_multiple_of(*interval) {
+                writeln!(samples, "{},{}", self.cycles, self.call_frames.len())?;
+            }
+        }
+
+        if let Some(addr) = self.register_break_hit {
+            outcome = Some(HaltReason::RegisterMatch { addr });
+        }
+        Ok(match (outcome, self.watch_hit.take()) {
+            (Some(outcome), _) => StepResult::Stopped(outcome),
+            (None, Some((location, old, new))) => StepResult::Watchpoint { location, old, new },
+            (None, None) => match self.self_modify_hit.take() {
+                Some((addr, old, new)) => StepResult::SelfModify { addr, old, new },
+                None => StepResult::Continue,
+            },
+        })
+    }
+
+    fn write_trace(&mut self, inst: &Instruction) -> Result<(), VmError> {
+        let Some(trace) = &mut self.trace else {
+            return Ok(());
+        };
+        match self.trace_format {
+            TraceFormat::Text => {
+                write!(
+                    trace,
+                    "{:04}: {}",
+                    self.current_pc,
+                    format_instruction(inst, &self.register_aliases)
+                )?;
+                for arg in [inst.a, inst.b, inst.c].into_iter().flatten() {
+                    if let Arg::Register(reg) = arg {
+                        let name = format_arg(arg, &self.register_aliases);
+                        write!(trace, " {name}={}", self.registers[reg])?;
+                    }
+                }
+                writeln!(trace)?;
+            }
+            TraceFormat::Json => {
+                let line = format_trace_json(self.current_pc, inst, &self.registers);
+                writeln!(trace, "{line}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes `step` fail with `VmError::InfiniteLoopDetected` when it is about
+    /// to execute from a `pc`, registers and stack it already executed from
+    /// within the last `window` instructions. A `wmem` or `in` changes state
+    /// the comparison doesn't see, so either one clears the window. Zero, the
+    /// default, turns detection off.
+    pub fn set_loop_detection(&mut self, window: usize) {
+        self.loop_window = window;
+        self.forget_states();
+    }
+
+    fn check_for_loop(&mut self) -> Result<(), VmError> {
+        let mut hasher = DefaultHasher::new();
+        (self.pc, self.registers, &self.stack).hash(&mut hasher);
+        let state = hasher.finish();
+
+        if self.recent_counts.contains_key(&state) {
+            return Err(VmError::InfiniteLoopDetected { pc: self.pc });
+        }
+        if self.recent_states.len() == self.loop_window {
+            if let Some(oldest) = self.recent_states.pop_front() {
+                if let Some(count) = self.recent_counts.get_mut(&oldest) {
+                    *count -= 1;
+                    if *count == 0 {
+                        self.recent_counts.remove(&oldest);
+                    }
+                }
+            }
+        }
+        self.recent_states.push_back(state);
+        *self.recent_counts.entry(state).or_default() += 1;
+        Ok(())
+    }
+
+    fn forget_states(&mut self) {
+        self.recent_states.clear();
+        self.recent_counts.clear();
+    }
+
+    /// Undoes the bookkeeping for an `in` that stopped before it ran, leaving
+    /// `pc` on it.
+    fn unstep(&mut self) {
+        self.pc = self.current_pc;
+        self.cycles -= 1;
+        if self.rewind_depth > 0 {
+            self.history.pop_back();
+        }
+    }
+
+    /// Keeps enough history to `step_back` over the last `depth` executed
+    /// instructions. Zero, the default, turns it off.
+    pub fn set_rewind_depth(&mut self, depth: usize) {
+        self.rewind_depth = depth;
+        self.history.truncate(depth);
+    }
+
+    /// The last `n` instructions in the rewind history, oldest first, as
+    /// `disassemble` lines. Each is decoded from memory as it is now.
+    pub fn history(&self, n: usize) -> Vec<String> {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(n))
+            .filter_map(|snapshot| {
+                self.disassemble(snapshot.pc..snapshot.pc + 1)
+                    .into_iter()
+                    .next()
+            })
+            .collect()
+    }
+
+    /// Undoes the most recently executed instruction still in the rewind
+    /// history. Input already consumed and output already written stay as
+    /// they are.
+    pub fn step_back(&mut self) -> Result<(), VmError> {
+        let snapshot = self.history.pop_back().ok_or(VmError::NothingToRewind)?;
+        if let Some((addr, old)) = snapshot.overwritten {
+            self.memory[addr] = old;
+            self.invalidate_decoded(addr);
+        }
+        self.registers = snapshot.registers;
+        self.stack = snapshot.stack;
+        self.call_frames = snapshot.call_frames;
+        self.pc = snapshot.pc;
+        self.cycles = snapshot.cycles;
+        Ok(())
+    }
+
+    /// Like `step`, except that a `call` runs its whole routine, stopping
+    /// once the stack is back to its depth before the call. Anything but
+    /// `StepResult::Continue` from the routine ends the step early, and the
+    /// `set_max_cycles` limit still applies, so a routine that never returns
+    /// can't hang it.
+    pub fn step_over(&mut self) -> Result<StepResult, VmError> {
+        let is_call = matches!(self.decode_at(self.pc), Ok((inst, _)) if inst.op == Op::Call);
+        let depth = self.stack.len();
+
+        let mut result = self.step()?;
+        while is_call && result == StepResult::Continue && self.stack.len() > depth {
+            if let Some(limit) = self.max_cycles.filter(|&limit| self.cycles >= limit) {
+                return Err(VmError::CycleLimitExceeded(limit));
+            }
+            result = self.step()?;
+        }
+        Ok(result)
+    }
+
+    /// Calls `step` up to `n` times, stopping early on anything but
+    /// `StepResult::Continue` or at a breakpoint, which is reported as
+    /// `HaltReason::Breakpoint` before it runs. Returns the last result along
+    /// with how many instructions ran.
+    pub fn step_n(&mut self, n: u64) -> Result<(StepResult, u64), VmError> {
+        let start = self.cycles;
+        let mut result = StepResult::Continue;
+
+        for taken in 0..n {
+            if taken > 0 && self.breakpoint_hit() {
+                result = StepResult::Stopped(HaltReason::Breakpoint { addr: self.pc });
+                break;
+            }
+            result = self.step()?;
+            if result != StepResult::Continue {
+                break;
+            }
+        }
+        Ok((result, self.cycles - start))
+    }
+
+    /// Executes instructions until the program stops, returning why.
+    pub fn run(&mut self) -> Result<HaltReason, VmError> {
+        self.run_loop(false)
+    }
+
+    /// Runs with `commands` as the only input and returns everything
+    /// printed, stopping at `halt` or once the commands run out. The VM's
+    /// own input and output are put back afterwards.
+    pub fn run_script(&mut self, commands: &str) -> Result<String, VmError> {
+        let buffer = SharedBuffer::new();
+        let output = std::mem::replace(&mut self.output, Box::new(buffer.clone()));
+        let sources = std::mem::replace(
+            &mut self.input_sources,
+            vec![InputSource::Queue(commands.as_bytes().to_vec())],
+        );
+
+        let result = self.run();
+        self.output = output;
+        self.input_sources = sources;
+        result?;
+
+        Ok(String::from_utf8_lossy(&buffer.contents()).into_owned())
+    }
+
+    /// Like `run`, but stops with `HaltReason::Breakpoint` before executing
+    /// the instruction at any breakpoint address. The instruction the VM is
+    /// currently sitting on always runs, so calling this again resumes.
+    pub fn run_until_break(&mut self) -> Result<HaltReason, VmError> {
+        self.run_loop(true)
+    }
+
+    fn run_loop(&mut self, use_breakpoints: bool) -> Result<HaltReason, VmError> {
+        let started = Instant::now();
+        let mut resuming = true;
+
+        loop {
+            if self.interrupt.load(Ordering::Relaxed) {
+                self.interrupt.store(false, Ordering::Relaxed);
+                return Ok(HaltReason::Interrupted);
+            }
+
+            if use_breakpoints && !resuming && self.breakpoint_hit() {
+                return Ok(HaltReason::Breakpoint { addr: self.pc });
+            }
+            resuming = false;
+
+            if let Some(limit) = self.time_limit {
+                if self.cycles.is_multiple_of(TIME_CHECK_INTERVAL) && started.elapsed() > limit {
+                    return Err(VmError::TimeLimitExceeded(limit));
+                }
+            }
+
+            if let Some(limit) = self.max_cycles.filter(|&limit| self.cycles >= limit) {
+                return Err(VmError::CycleLimitExceeded(limit));
+            }
+
+            match self.step()? {
+                StepResult::Continue => (),
+                StepResult::Watchpoint { location, old, new } => {
+                    if use_breakpoints {
+                        return Ok(HaltReason::Watchpoint { location, old, new });
+                    }
+                }
+                StepResult::SelfModify { addr, old, new } => eprintln!(
+                    "warning: write to executed code at {addr}: {old} -> {new} (pc {})",
+                    self.current_pc
+                ),
+                StepResult::Stopped(outcome) => return Ok(outcome),
+            }
+        }
+    }
+}
+
+/// Runs `f`, returning its result along with how long it took.
+pub fn timed<T>(f: impl FnOnce() -> T) -> (T, Duration) {
+    let started = Instant::now();
+    let result = f();
+    (result, started.elapsed())
+}
+
+/// How long each phase of `VM::timed_run` took.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PhaseTimes {
+    pub load: Duration,
+    pub run: Duration,
+}
+
+impl VM {
+    /// Loads `program` and runs it, timing the two phases separately.
+    pub fn timed_run(&mut self, program: &[u8]) -> Result<(HaltReason, PhaseTimes), VmError> {
+        let (loaded, load) = timed(|| self.load(program));
+        loaded?;
+        let (result, run) = timed(|| self.run());
+        Ok((result?, PhaseTimes { load, run }))
+    }
+}
+
+/// The cycle limit `run_bytes` runs under.
+pub const RUN_BYTES_MAX_CYCLES: u64 = 100_000;
+
+/// Loads `program`, runs it with exactly `input` to read and returns what it
+/// wrote, stopping after `RUN_BYTES_MAX_CYCLES` instructions. Meant as a fuzz
+/// target: any `program` or `input` ends in output or an error, never a
+/// panic, and nothing reaches stdin, stdout or stderr.
+pub fn run_bytes(program: &[u8], input: &[u8]) -> Result<Vec<u8>, VmError> {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(input)
+        .output(Box::new(output.clone()))
+        .max_cycles(RUN_BYTES_MAX_CYCLES)
+        .build();
+    vm.load(program)?;
+    vm.run()?;
+    Ok(output.take())
+}
+
+/// The cycle limit `run_source` runs under.
+pub const RUN_SOURCE_MAX_CYCLES: u64 = 1_000_000;
+
+/// Assembles `source`, runs it in a fresh VM with `input` as the only input
+/// and returns everything it printed, as `run_script` does. Assembly and
+/// runtime errors both come back as their messages, including hitting
+/// `RUN_SOURCE_MAX_CYCLES`, so a program that never halts fails rather than
+/// hanging.
+pub fn run_source(source: &str, input: &str) -> Result<String, String> {
+    let program = assemble(source)?;
+    let mut vm = VM::new();
+    vm.set_max_cycles(RUN_SOURCE_MAX_CYCLES);
+    vm.load(&program).map_err(|err| err.to_string())?;
+    vm.run_script(input).map_err(|err| err.to_string())
+}
+
+/// How a case given to `run_cases` went.
+#[derive(Debug)]
+pub enum CaseResult {
+    /// The output matched the expected transcript exactly.
+    Pass,
+    /// The output first differed from the expected transcript at byte
+    /// `offset`, which is the shorter length if one is a prefix of the other.
+    Mismatch { offset: usize, output: Vec<u8> },
+    /// The program failed before its output could be compared.
+    Error(VmError),
+}
+
+/// Runs `program` once per `(input, expected)` case, each in a fresh VM that
+/// reads exactly `input`, and compares everything it writes against
+/// `expected`.
+pub fn run_cases(program: &[u8], cases: &[(&[u8], &[u8])]) -> Vec<CaseResult> {
+    cases
+        .iter()
+        .map(|&(input, expected)| {
+            let output = SharedBuffer::new();
+            let mut vm = VM::builder()
+                .replay(input)
+                .output(Box::new(output.clone()))
+                .build();
+            if let Err(err) = vm.load(program).and_then(|_| vm.run()) {
+                return CaseResult::Error(err);
+            }
+
+            let output = output.take();
+            match output.iter().zip(expected).position(|(a, b)| a != b) {
+                None if output.len() == expected.len() => CaseResult::Pass,
+                offset => CaseResult::Mismatch {
+                    offset: offset.unwrap_or(output.len().min(expected.len())),
+                    output,
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `inst` by planting it in the decode cache at address 0, since a
+    /// register past r7 can't be encoded in memory.
+    fn run_instruction(inst: Instruction) -> Result<StepResult, VmError> {
+        let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+        vm.set_decode_cache(true);
+        if let Some(cache) = &mut vm.decoded {
+            cache[0] = Some((inst, inst.len()));
+        }
+        vm.step()
+    }
+
+    #[test]
+    fn out_of_range_registers_are_an_error() {
+        let mut set = Instruction::new(Op::Set);
+        set.a = Some(Arg::Register(8));
+        set.b = Some(Arg::Literal(1));
+        assert!(matches!(run_instruction(set), Err(VmError::BadRegister(8))));
+
+        let mut out = Instruction::new(Op::Out);
+        out.a = Some(Arg::Register(8));
+        assert!(matches!(run_instruction(out), Err(VmError::BadRegister(8))));
+    }
+}
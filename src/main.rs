@@ -1,364 +1,743 @@
-use std::fs;
-
-#[repr(u8)]
-#[derive(Debug)]
-enum Op {
-    Halt,
-    Set,
-    Push,
-    Pop,
-    Eq,
-    Gt,
-    Jmp,
-    Jt,
-    Jf,
-    Add,
-    Mult,
-    Mod,
-    And,
-    Or,
-    Not,
-    Rmem,
-    Wmem,
-    Call,
-    Ret,
-    Out,
-    In,
-    Noop,
-}
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
 
-impl TryFrom<u16> for Op {
-    type Error = String;
-
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Self::Halt),
-            1 => Ok(Self::Set),
-            2 => Ok(Self::Push),
-            3 => Ok(Self::Pop),
-            4 => Ok(Self::Eq),
-            5 => Ok(Self::Gt),
-            6 => Ok(Self::Jmp),
-            7 => Ok(Self::Jt),
-            8 => Ok(Self::Jf),
-            9 => Ok(Self::Add),
-            10 => Ok(Self::Mult),
-            11 => Ok(Self::Mod),
-            12 => Ok(Self::And),
-            13 => Ok(Self::Or),
-            14 => Ok(Self::Not),
-            15 => Ok(Self::Rmem),
-            16 => Ok(Self::Wmem),
-            17 => Ok(Self::Call),
-            18 => Ok(Self::Ret),
-            19 => Ok(Self::Out),
-            20 => Ok(Self::In),
-            21 => Ok(Self::Noop),
-            _ => Err(format!("received unknown op code: {value}")),
-        }
-    }
-}
+use synacor::{
+    codes, decompile_routine, diff_states, timed, BlankLinePolicy, ByteOrder, Fault, HaltReason,
+    Op, StepResult, Tee, TraceFormat, VmError, VmState, MEMORY_SIZE, OPCODES, VM,
+};
 
-#[derive(Debug)]
-enum Arg {
-    Literal(u16),
-    Register(usize),
+#[derive(Default)]
+struct Options {
+    program: Option<String>,
+    fault_addr: Option<usize>,
+    fault_bit: Option<u8>,
+    fault_cycle: Option<u64>,
+    cfg: bool,
+    cfg_dot: Option<String>,
+    io_log: Option<String>,
+    log: Option<String>,
+    log_input: bool,
+    responses: Vec<(String, String)>,
+    macros: Vec<(String, String)>,
+    time_limit: Option<Duration>,
+    max_cycles: Option<u64>,
+    detect_loops: Option<usize>,
+    stats: bool,
+    dump_state: bool,
+    verify_checksum: Option<u64>,
+    echo_input: bool,
+    hot: Option<usize>,
+    op_histogram: bool,
+    dispatch: Option<usize>,
+    stop_when: Option<(usize, u16)>,
+    reg_csv: Option<String>,
+    trace: Option<String>,
+    trace_format: TraceFormat,
+    byte_order: ByteOrder,
+    find_outs: Option<usize>,
+    search: Option<Vec<u16>>,
+    soft_halt: bool,
+    depth_profile: Option<String>,
+    sample_every: Option<u64>,
+    dump_nonzero: bool,
+    input: Option<String>,
+    script: Option<String>,
+    time: bool,
+    decode_cache: bool,
+    warn_uninit: bool,
+    banner: bool,
+    break_on_code_write: bool,
+    warn_self_modify: bool,
+    call_log: Option<String>,
+    code_size: bool,
+    codes: bool,
+    decompile: Option<usize>,
+    find_confirmation: bool,
+    verify: bool,
+    disasm: Option<(usize, usize)>,
+    literals: Option<Op>,
+    blank_line_policy: BlankLinePolicy,
+    load_state: Option<String>,
+    save_state: Option<String>,
+    snapshot_at_input: Option<String>,
+    diff_states: Option<(String, String)>,
+    debug: bool,
+    pokes: Vec<(usize, u16)>,
+    set_regs: Vec<(usize, u16)>,
 }
 
-impl TryFrom<u16> for Arg {
-    type Error = String;
-
-    fn try_from(value: u16) -> Result<Self, Self::Error> {
-        match value {
-            0..=32767 => Ok(Self::Literal(value)),
-            32768 => Ok(Self::Register(0)),
-            32769 => Ok(Self::Register(1)),
-            32770 => Ok(Self::Register(2)),
-            32771 => Ok(Self::Register(3)),
-            32772 => Ok(Self::Register(4)),
-            32773 => Ok(Self::Register(5)),
-            32774 => Ok(Self::Register(6)),
-            32775 => Ok(Self::Register(7)),
-            _ => Err(format!("unable to convert {value} to argument")),
-        }
-    }
+fn flag_value<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let value = value.ok_or(format!("missing value for {flag}"))?;
+    value
+        .parse()
+        .or(Err(format!("invalid value for {flag}: {value}")))
 }
 
-#[derive(Debug)]
-struct Instruction {
-    op: Op,
-    a: Option<Arg>,
-    b: Option<Arg>,
-    c: Option<Arg>,
-}
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = env::args().skip(1);
 
-impl Instruction {
-    fn new(op: Op) -> Self {
-        Self {
-            op,
-            a: None,
-            b: None,
-            c: None,
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fault-addr" => options.fault_addr = Some(flag_value(&arg, args.next())?),
+            "--fault-bit" => options.fault_bit = Some(flag_value(&arg, args.next())?),
+            "--fault-cycle" => options.fault_cycle = Some(flag_value(&arg, args.next())?),
+            "--cfg" => options.cfg = true,
+            "--cfg-dot" => options.cfg_dot = Some(flag_value(&arg, args.next())?),
+            "--io-log" => options.io_log = Some(flag_value(&arg, args.next())?),
+            "--log" => options.log = Some(flag_value(&arg, args.next())?),
+            "--log-input" => options.log_input = true,
+            "--respond" => {
+                let mapping: String = flag_value(&arg, args.next())?;
+                let (prompt, response) = mapping
+                    .split_once('=')
+                    .ok_or(format!("expected PROMPT=RESPONSE for {arg}: {mapping}"))?;
+                options
+                    .responses
+                    .push((prompt.to_string(), response.to_string()));
+            }
+            "--macro" => {
+                let mapping: String = flag_value(&arg, args.next())?;
+                let (name, expansion) = mapping
+                    .split_once('=')
+                    .ok_or(format!("expected NAME=EXPANSION for {arg}: {mapping}"))?;
+                options
+                    .macros
+                    .push((name.to_string(), expansion.to_string()));
+            }
+            "--time-limit" => {
+                let seconds: f64 = flag_value(&arg, args.next())?;
+                let limit = Duration::try_from_secs_f64(seconds)
+                    .map_err(|err| format!("invalid value for {arg}: {seconds} ({err})"))?;
+                options.time_limit = Some(limit);
+            }
+            "--max-cycles" => options.max_cycles = Some(flag_value(&arg, args.next())?),
+            "--detect-loops" => options.detect_loops = Some(flag_value(&arg, args.next())?),
+            "--stats" => options.stats = true,
+            "--dump-state" => options.dump_state = true,
+            "--verify-checksum" => {
+                let hex: String = flag_value(&arg, args.next())?;
+                let checksum = u64::from_str_radix(hex.trim_start_matches("0x"), 16)
+                    .or(Err(format!("expected a hex checksum for {arg}: {hex}")))?;
+                options.verify_checksum = Some(checksum);
+            }
+            "--echo-input" => options.echo_input = true,
+            "--hot" => options.hot = Some(flag_value(&arg, args.next())?),
+            "--op-histogram" => options.op_histogram = true,
+            "--dispatch" => options.dispatch = Some(flag_value(&arg, args.next())?),
+            "--stop-when" => {
+                let condition: String = flag_value(&arg, args.next())?;
+                let parsed = condition.split_once('=').and_then(|(reg, value)| {
+                    let reg = reg.strip_prefix('r')?.parse().ok().filter(|&reg| reg < 8)?;
+                    Some((reg, value.parse().ok()?))
+                });
+                options.stop_when =
+                    Some(parsed.ok_or(format!("expected rN=VALUE for {arg}: {condition}"))?);
+            }
+            "--reg-csv" => options.reg_csv = Some(flag_value(&arg, args.next())?),
+            "--trace" => options.trace = Some(flag_value(&arg, args.next())?),
+            "--trace-format" => {
+                options.trace_format = match flag_value::<String>(&arg, args.next())?.as_str() {
+                    "text" => TraceFormat::Text,
+                    "json" => TraceFormat::Json,
+                    other => return Err(format!("expected text or json for {arg}: {other}")),
+                }
+            }
+            "--byte-order" => {
+                options.byte_order = match flag_value::<String>(&arg, args.next())?.as_str() {
+                    "little" => ByteOrder::Little,
+                    "big" => ByteOrder::Big,
+                    other => return Err(format!("expected little or big for {arg}: {other}")),
+                }
+            }
+            "--find-outs" => options.find_outs = Some(flag_value(&arg, args.next())?),
+            "--search" => {
+                // Comma-separated words, or else text matched one low byte per
+                // word, the way `out` prints it.
+                let pattern: String = flag_value(&arg, args.next())?;
+                let words = pattern
+                    .split(',')
+                    .map(|word| word.trim().parse::<u16>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap_or_else(|_| pattern.bytes().map(u16::from).collect());
+                options.search = Some(words);
+            }
+            "--soft-halt" => options.soft_halt = true,
+            "--depth-profile" => options.depth_profile = Some(flag_value(&arg, args.next())?),
+            "--sample-every" => options.sample_every = Some(flag_value(&arg, args.next())?),
+            "--dump-nonzero" => options.dump_nonzero = true,
+            "--input" => options.input = Some(flag_value(&arg, args.next())?),
+            "--script" => options.script = Some(flag_value(&arg, args.next())?),
+            "--time" => options.time = true,
+            "--decode-cache" => options.decode_cache = true,
+            "--warn-uninit" => options.warn_uninit = true,
+            "--banner" => options.banner = true,
+            "--break-on-code-write" => options.break_on_code_write = true,
+            "--warn-self-modify" => options.warn_self_modify = true,
+            "--call-log" => options.call_log = Some(flag_value(&arg, args.next())?),
+            "--code-size" => options.code_size = true,
+            "--codes" => options.codes = true,
+            "--decompile" => options.decompile = Some(flag_value(&arg, args.next())?),
+            "--find-confirmation" => options.find_confirmation = true,
+            "--verify" => options.verify = true,
+            "--disasm" => {
+                let start: usize = flag_value(&arg, args.next())?;
+                let len: usize = flag_value(&arg, args.next())?;
+                if start.checked_add(len).is_none_or(|end| end > MEMORY_SIZE) {
+                    return Err(format!(
+                        "{arg} range {start}+{len} is outside memory of {MEMORY_SIZE} words"
+                    ));
+                }
+                options.disasm = Some((start, len));
+            }
+            "--literals" => {
+                let mnemonic: String = flag_value(&arg, args.next())?;
+                let spec = OPCODES
+                    .iter()
+                    .find(|spec| spec.mnemonic == mnemonic)
+                    .ok_or(format!("unknown mnemonic for {arg}: {mnemonic}"))?;
+                options.literals = Some(spec.op);
+            }
+            "--blank-line" => {
+                options.blank_line_policy = match flag_value::<String>(&arg, args.next())?.as_str()
+                {
+                    "deliver" => BlankLinePolicy::Deliver,
+                    "end" => BlankLinePolicy::EndInput,
+                    other => return Err(format!("expected deliver or end for {arg}: {other}")),
+                }
+            }
+            "--load-state" => options.load_state = Some(flag_value(&arg, args.next())?),
+            "--save-state" => options.save_state = Some(flag_value(&arg, args.next())?),
+            "--snapshot-at-input" => {
+                options.snapshot_at_input = Some(flag_value(&arg, args.next())?)
+            }
+            "--diff-states" => {
+                let before = flag_value(&arg, args.next())?;
+                let after = flag_value(&arg, args.next())?;
+                options.diff_states = Some((before, after));
+            }
+            "--debug" => options.debug = true,
+            "--poke" => {
+                let poke: String = flag_value(&arg, args.next())?;
+                let parsed = poke.split_once('=').and_then(|(addr, value)| {
+                    let addr = addr.parse().ok().filter(|&addr| addr < MEMORY_SIZE)?;
+                    Some((addr, value.parse().ok()?))
+                });
+                options
+                    .pokes
+                    .push(parsed.ok_or(format!("expected ADDR=VALUE for {arg}: {poke}"))?);
+            }
+            "--set-reg" => {
+                let assignment: String = flag_value(&arg, args.next())?;
+                let parsed = assignment.split_once('=').and_then(|(reg, value)| {
+                    let reg = reg.strip_prefix('r').unwrap_or(reg);
+                    let reg = reg.parse().ok().filter(|&reg| reg < 8)?;
+                    Some((reg, value.parse().ok()?))
+                });
+                options
+                    .set_regs
+                    .push(parsed.ok_or(format!("expected N=VALUE for {arg}: {assignment}"))?);
+            }
+            _ if arg.starts_with("--") => return Err(format!("unknown argument: {arg}")),
+            _ if options.program.is_none() => options.program = Some(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
         }
     }
-}
 
-const MEMORY_SIZE: usize = 0b0111_1111_1111_1111;
-
-struct VM {
-    memory: [u16; MEMORY_SIZE],
-    registers: [u16; 8],
-    stack: Vec<u16>,
-    pc: usize,
-}
-
-impl VM {
-    fn new() -> Self {
-        Self {
-            memory: [0; MEMORY_SIZE],
-            registers: [0; 8],
-            stack: Vec::new(),
-            pc: 0,
+    match (options.fault_addr, options.fault_bit) {
+        (Some(addr), Some(bit)) => {
+            if addr >= MEMORY_SIZE {
+                return Err(format!(
+                    "--fault-addr {addr} is outside memory of {MEMORY_SIZE} words"
+                ));
+            }
+            if bit > 15 {
+                return Err(format!("--fault-bit {bit} is outside a 16-bit word"));
+            }
         }
+        (None, None) if options.fault_cycle.is_some() => {
+            return Err("--fault-cycle needs --fault-addr and --fault-bit".to_string())
+        }
+        (None, None) => (),
+        _ => return Err("--fault-addr and --fault-bit must be given together".to_string()),
     }
 
-    fn load(&mut self, bytes: &[u8]) -> Result<(), String> {
-        for (slice, dest) in bytes.chunks(2).zip(self.memory.iter_mut()) {
-            match slice.get(0..2) {
-                Some(&[lo, hi]) => *dest = ((hi as u16) << 8) | (lo as u16),
-                _ => return Err("failed to load file".to_string()),
-            }
-        }
+    Ok(options)
+}
 
-        Ok(())
-    }
+const DEBUG_HELP: &str =
+    "commands: s [N], n, history [N], back, c, b ADDR [EXPR], r, alias REG NAME, m ADDR LEN, asm ADDR INST, p EXPR, stack, bt, q";
 
-    fn read_next(&mut self) -> Result<u16, String> {
-        let value = *self.memory.get(self.pc).ok_or("failed to get next u16")?;
-        self.pc += 1;
-        Ok(value)
-    }
+/// How many instructions `history` lists when not given a count.
+const DEBUG_HISTORY_LEN: usize = 10;
 
-    fn read_argument(&mut self) -> Result<Arg, String> {
-        self.read_next()?.try_into()
-    }
+/// How many instructions `back` can undo in the debugger.
+const DEBUG_REWIND_DEPTH: usize = 10_000;
 
-    fn read_instruction(&mut self) -> Result<Instruction, String> {
-        let op = self.read_next()?.try_into()?;
-        let mut inst = Instruction::new(op);
+/// Drives `vm` from debugger commands read on stdin, showing the next
+/// instruction before each prompt, until `q` or the program stops.
+fn debug(vm: &mut VM) -> Result<(), VmError> {
+    let stdin = io::stdin();
+    let mut line = String::new();
+    vm.set_rewind_depth(DEBUG_REWIND_DEPTH);
 
-        match inst.op {
-            Op::Halt | Op::Noop | Op::Ret => (),
-            Op::Out | Op::Jmp | Op::Push | Op::Pop | Op::Call | Op::In => {
-                inst.a = Some(self.read_argument()?);
-            }
-            Op::Jt | Op::Jf | Op::Set | Op::Not | Op::Rmem | Op::Wmem => {
-                inst.a = Some(self.read_argument()?);
-                inst.b = Some(self.read_argument()?);
-            }
-            Op::Add | Op::Eq | Op::Gt | Op::And | Op::Or | Op::Mult | Op::Mod => {
-                inst.a = Some(self.read_argument()?);
-                inst.b = Some(self.read_argument()?);
-                inst.c = Some(self.read_argument()?);
-            }
+    loop {
+        if let Some(text) = vm.disassemble(vm.pc()..vm.pc() + 1).first() {
+            println!("{text}");
         }
+        print!("(debug) ");
+        io::stdout().flush()?;
 
-        Ok(inst)
-    }
-
-    #[inline(always)]
-    fn get_value(&self, arg: Arg) -> u16 {
-        match arg {
-            Arg::Literal(value) => value,
-            Arg::Register(reg) => self.registers[reg],
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            return Ok(());
         }
-    }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |index: usize| words.get(index).and_then(|word| word.parse::<usize>().ok());
 
-    fn run(&mut self) -> Result<(), String> {
-        let mut input = String::new();
-        let mut chars = input.chars();
-
-        while self.pc < self.memory.len() {
-            let instruction = self.read_instruction()?;
-            match instruction {
-                Instruction { op: Op::Halt, .. } => break,
-                Instruction { op: Op::Noop, .. } => continue,
-                Instruction { op: Op::Ret, .. } => {
-                    if let Some(value) = self.stack.pop() {
-                        self.pc = value as usize
-                    } else {
-                        break;
-                    }
+        let stopped = match words[..] {
+            ["s"] => match vm.step()? {
+                StepResult::Continue => None,
+                StepResult::Watchpoint { location, old, new } => {
+                    println!("{location:?}: {old} -> {new}");
+                    None
+                }
+                StepResult::SelfModify { addr, old, new } => {
+                    println!("code write at {addr}: {old} -> {new}");
+                    None
                 }
-                Instruction {
-                    op: Op::Out,
-                    a: Some(arg),
-                    ..
-                } => {
-                    let value = self.get_value(arg);
-                    if let Ok(ch) = char::try_from(value as u8) {
-                        print!("{ch}")
-                    } else {
-                        return Err(format!("failed to cast {value} to char"));
+                StepResult::Stopped(reason) => Some(reason),
+            },
+            ["s", _] => match number(1) {
+                Some(count) => match vm.step_n(count as u64)? {
+                    (StepResult::Stopped(reason @ HaltReason::Breakpoint { .. }), _) => {
+                        println!("{reason:?}");
+                        None
+                    }
+                    (StepResult::Stopped(reason), _) => Some(reason),
+                    (StepResult::Continue, _) => None,
+                    (other, ran) => {
+                        println!("{other:?} after {ran}");
+                        None
                     }
+                },
+                None => {
+                    println!("{DEBUG_HELP}");
+                    None
                 }
-                Instruction {
-                    op: Op::Jmp,
-                    a: Some(arg),
-                    ..
-                } => self.pc = self.get_value(arg) as usize,
-                Instruction {
-                    op: Op::Push,
-                    a: Some(arg),
-                    ..
-                } => self.stack.push(self.get_value(arg)),
-                Instruction {
-                    op: Op::Pop,
-                    a: Some(Arg::Register(a)),
-                    ..
-                } => {
-                    if let Some(value) = self.stack.pop() {
-                        self.registers[a] = value;
-                    } else {
-                        return Err(format!("called pop on an empty stack"));
+            },
+            ["n"] => match vm.step_over()? {
+                StepResult::Stopped(reason) => Some(reason),
+                StepResult::Continue => None,
+                other => {
+                    println!("{other:?}");
+                    None
+                }
+            },
+            ["history"] | ["history", _] => {
+                match words.get(1).map_or(Some(DEBUG_HISTORY_LEN), |_| number(1)) {
+                    Some(count) => {
+                        for text in vm.history(count) {
+                            println!("{text}");
+                        }
                     }
+                    None => println!("{DEBUG_HELP}"),
                 }
-                Instruction {
-                    op: Op::Call,
-                    a: Some(a),
-                    ..
-                } => {
-                    self.stack.push(self.pc as u16);
-                    self.pc = self.get_value(a) as usize
+                None
+            }
+            ["back"] => {
+                if let Err(err) = vm.step_back() {
+                    println!("{err}");
+                }
+                None
+            }
+            ["c"] => match vm.run_until_break()? {
+                reason @ (HaltReason::Breakpoint { .. } | HaltReason::Watchpoint { .. }) => {
+                    println!("{reason:?}");
+                    None
                 }
-                Instruction {
-                    op: Op::In,
-                    a: Some(Arg::Register(reg)),
-                    ..
-                } => {
-                    self.registers[reg] = match chars.next() {
-                        Some(ch) => ch,
-                        None => {
-                            input.clear();
-                            std::io::stdin()
-                                .read_line(&mut input)
-                                .or(Err("failed to read from stdin"))?;
-                            chars = input.chars();
-                            match chars.next() {
-                                Some(ch) => ch,
-                                None => return Ok(()),
-                            }
+                reason => Some(reason),
+            },
+            ["b", _] => {
+                match number(1) {
+                    Some(addr) => vm.add_breakpoint(addr),
+                    None => println!("{DEBUG_HELP}"),
+                }
+                None
+            }
+            ["b", _, ref condition @ ..] => {
+                match number(1) {
+                    Some(addr) => {
+                        if let Err(err) = vm.add_conditional_breakpoint(addr, &condition.join(" "))
+                        {
+                            println!("{err}");
                         }
-                    } as u16;
+                    }
+                    None => println!("{DEBUG_HELP}"),
                 }
-                Instruction {
-                    op: Op::Jt,
-                    a: Some(a),
-                    b: Some(b),
-                    ..
-                } => {
-                    if self.get_value(a) != 0 {
-                        self.pc = self.get_value(b) as usize
+                None
+            }
+            ["r"] => {
+                for (reg, value) in vm.registers().iter().enumerate() {
+                    match vm.register_alias(reg) {
+                        Some(name) => println!("r{reg} ({name}) = {value}"),
+                        None => println!("r{reg} = {value}"),
                     }
                 }
-                Instruction {
-                    op: Op::Jf,
-                    a: Some(a),
-                    b: Some(b),
-                    ..
-                } => {
-                    if self.get_value(a) == 0 {
-                        self.pc = self.get_value(b) as usize
+                None
+            }
+            ["alias", _, name] => {
+                match number(1) {
+                    Some(reg) => {
+                        if let Err(err) = vm.set_register_alias(reg, name) {
+                            println!("{err}");
+                        }
                     }
+                    None => println!("{DEBUG_HELP}"),
+                }
+                None
+            }
+            ["m", _, _] => {
+                match (number(1), number(2)) {
+                    (Some(addr), Some(len)) => print!("{}", vm.dump_memory(addr, len)),
+                    _ => println!("{DEBUG_HELP}"),
                 }
-                Instruction {
-                    op: Op::Set,
-                    a: Some(Arg::Register(reg)),
-                    b: Some(b),
-                    ..
-                } => self.registers[reg] = self.get_value(b),
-                Instruction {
-                    op: Op::Not,
-                    a: Some(Arg::Register(reg)),
-                    b: Some(b),
-                    ..
-                } => self.registers[reg] = !(!0b0111_1111_1111_1111 | self.get_value(b)),
-                Instruction {
-                    op: Op::Rmem,
-                    a: Some(Arg::Register(reg)),
-                    b: Some(b),
-                    ..
-                } => self.registers[reg] = self.memory[self.get_value(b) as usize],
-                Instruction {
-                    op: Op::Wmem,
-                    a: Some(a),
-                    b: Some(b),
-                    ..
-                } => self.memory[self.get_value(a) as usize] = self.get_value(b),
-                Instruction {
-                    op: Op::Add,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => self.registers[a] = (self.get_value(b) + self.get_value(c)) % 32768,
-                Instruction {
-                    op: Op::Eq,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => {
-                    let value_b = self.get_value(b);
-                    let value_c = self.get_value(c);
-                    self.registers[a] = if value_b == value_c { 1 } else { 0 }
+                None
+            }
+            ["asm", _, ref inst @ ..] => {
+                match number(1) {
+                    Some(addr) => {
+                        if let Err(err) = vm.patch_asm(addr, inst.join(" ").trim_matches('"')) {
+                            println!("{err}");
+                        }
+                    }
+                    None => println!("{DEBUG_HELP}"),
                 }
-                Instruction {
-                    op: Op::Gt,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => {
-                    let value_b = self.get_value(b);
-                    let value_c = self.get_value(c);
-                    self.registers[a] = if value_b > value_c { 1 } else { 0 }
+                None
+            }
+            ["p", _, ..] => {
+                match vm.eval_expr(&line.trim_start()[1..]) {
+                    Ok(value) => println!("{value}"),
+                    Err(err) => println!("{err}"),
                 }
-                Instruction {
-                    op: Op::And,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => self.registers[a] = self.get_value(b) & self.get_value(c),
-                Instruction {
-                    op: Op::Or,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => self.registers[a] = self.get_value(b) | self.get_value(c),
-                Instruction {
-                    op: Op::Mult,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => {
-                    self.registers[a] =
-                        (self.get_value(b) as u32 * self.get_value(c) as u32) as u16 % 32768
+                None
+            }
+            ["stack"] => {
+                println!("{:?}", vm.stack());
+                None
+            }
+            ["bt"] => {
+                for ret in vm.backtrace() {
+                    println!("{ret}");
                 }
-                Instruction {
-                    op: Op::Mod,
-                    a: Some(Arg::Register(a)),
-                    b: Some(b),
-                    c: Some(c),
-                } => self.registers[a] = self.get_value(b) % self.get_value(c),
-                _ => return Err(format!("unable to handle instruction: {instruction:?}")),
+                None
+            }
+            ["q"] => return Ok(()),
+            _ => {
+                println!("{DEBUG_HELP}");
+                None
             }
+        };
+
+        if let Some(reason) = stopped {
+            println!("run ended: {reason:?}");
+            return Ok(());
         }
+    }
+}
 
-        Ok(())
+/// Prints how the state saved at `after` differs from the one at `before`.
+fn print_state_diff(before: &Path, after: &Path) -> Result<(), VmError> {
+    let diff = diff_states(&VmState::read(before)?, &VmState::read(after)?);
+    for (reg, old, new) in &diff.registers {
+        println!("r{reg}: {old} -> {new}");
+    }
+    if let Some((old, new)) = diff.pc {
+        println!("pc: {old} -> {new}");
+    }
+    if let Some((old, new)) = &diff.stack {
+        println!("stack: {old:?} -> {new:?}");
+    }
+    for (addr, old, new) in &diff.memory {
+        println!("{addr}: {old} -> {new}");
     }
+    Ok(())
 }
 
 fn main() {
-    let mut vm = VM::new();
-    let program = fs::read("challenge.bin").expect("failed to read file");
-    vm.load(&program).unwrap();
-    vm.run().unwrap();
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2);
+    });
+
+    if let Some((before, after)) = &options.diff_states {
+        print_state_diff(Path::new(before), Path::new(after)).unwrap();
+        return;
+    }
+
+    let path = options.program.as_deref().unwrap_or("challenge.bin");
+    let program = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("failed to read {path}: {err}");
+        process::exit(1);
+    });
+
+    let mut vm = match &options.log {
+        Some(path) => {
+            let log = File::create(path).expect("failed to create log");
+            let mut builder = VM::builder().output(Box::new(Tee::new(
+                io::stdout(),
+                log.try_clone().expect("failed to open log"),
+            )));
+            if options.log_input {
+                builder = builder.input(Box::new(Tee::new(BufReader::new(io::stdin()), log)));
+            }
+            builder.build()
+        }
+        None => VM::new(),
+    };
+    vm.set_byte_order(options.byte_order);
+    let (loaded, load_time) = timed(|| vm.load(&program));
+    loaded.unwrap();
+
+    if let Some(expected) = options.verify_checksum {
+        let checksum = vm.program_checksum();
+        if checksum != expected {
+            eprintln!(
+                "checksum mismatch for {path}: expected {expected:016x}, got {checksum:016x}"
+            );
+            process::exit(1);
+        }
+    }
+
+    if let Some(path) = &options.load_state {
+        vm.load_state(Path::new(path)).unwrap();
+    }
+
+    if let Some(path) = options.snapshot_at_input {
+        vm.set_snapshot_at_input(path.into());
+    }
+
+    for &(addr, value) in &options.pokes {
+        vm.poke_mem(addr, value).unwrap();
+    }
+    for &(reg, value) in &options.set_regs {
+        vm.set_register(reg, value).unwrap();
+    }
+
+    if let (Some(addr), Some(bit)) = (options.fault_addr, options.fault_bit) {
+        vm.inject_fault(Fault {
+            addr,
+            bit,
+            cycle: options.fault_cycle,
+        })
+        .unwrap();
+    }
+
+    if options.cfg || options.cfg_dot.is_some() {
+        let cfg = vm.control_flow_graph(0);
+        println!("blocks: {}, edges: {}", cfg.blocks.len(), cfg.edges.len());
+        if let Some(path) = options.cfg_dot {
+            fs::write(path, cfg.to_dot()).expect("failed to write dot file");
+        }
+        return;
+    }
+
+    if options.dump_nonzero {
+        print!("{}", vm.dump_nonzero());
+        return;
+    }
+
+    if let Some(op) = options.literals {
+        for (addr, value) in vm.literal_operands(op) {
+            println!("{addr}: {value}");
+        }
+        return;
+    }
+
+    if let Some((start, len)) = options.disasm {
+        for line in vm.disassemble_labeled(start..start + len, &HashMap::new()) {
+            println!("{line}");
+        }
+        return;
+    }
+
+    if let Some(start) = options.decompile {
+        print!("{}", decompile_routine(vm.memory(), start));
+        return;
+    }
+
+    if options.verify {
+        if let Err(errors) = vm.verify() {
+            for err in errors {
+                eprintln!("{err}");
+            }
+            process::exit(1);
+        }
+        return;
+    }
+
+    if options.codes {
+        for code in codes::scan_binary_for_codes(vm.memory()) {
+            println!("{code}");
+        }
+        return;
+    }
+
+    if options.find_confirmation {
+        for entry in vm.confirmation_candidates() {
+            println!("{entry}");
+        }
+        return;
+    }
+
+    if options.code_size {
+        let (count, addr) = vm.code_extent();
+        println!("{count} instructions before the first undecodable word at {addr}");
+        return;
+    }
+
+    if let Some(min_len) = options.find_outs {
+        for (addr, text) in vm.out_literal_runs(min_len) {
+            println!("{addr}: {text:?}");
+        }
+        return;
+    }
+
+    if let Some(pattern) = &options.search {
+        for addr in vm.search(pattern) {
+            println!("{addr}");
+        }
+        return;
+    }
+
+    if let Some(addr) = options.dispatch {
+        for target in vm.dispatch_targets(addr).unwrap() {
+            println!("{target}");
+        }
+        return;
+    }
+
+    if let Some(path) = options.io_log {
+        let file = File::create(path).expect("failed to create io log");
+        vm.set_io_log(Box::new(BufWriter::new(file)));
+    }
+
+    for (prompt, response) in &options.responses {
+        vm.add_prompt_response(prompt, response);
+    }
+
+    for (name, expansion) in &options.macros {
+        vm.add_macro(name, expansion);
+    }
+
+    if let Some(limit) = options.time_limit {
+        vm.set_time_limit(limit);
+    }
+
+    if let Some(limit) = options.max_cycles {
+        vm.set_max_cycles(limit);
+    }
+
+    if let Some(window) = options.detect_loops {
+        vm.set_loop_detection(window);
+    }
+
+    if let Some(path) = options.reg_csv {
+        let file = File::create(path).expect("failed to create register csv");
+        vm.set_reg_csv(Box::new(BufWriter::new(file))).unwrap();
+    }
+
+    if let Some(path) = options.trace {
+        let file = File::create(path).expect("failed to create trace");
+        vm.set_trace(Box::new(BufWriter::new(file)));
+        vm.set_trace_format(options.trace_format);
+    }
+
+    if let Some(path) = options.call_log {
+        let file = File::create(path).expect("failed to create call log");
+        vm.set_call_log(Box::new(BufWriter::new(file)));
+    }
+
+    if let Some(path) = options.depth_profile {
+        let file = File::create(path).expect("failed to create depth profile");
+        let interval = options.sample_every.unwrap_or(1000);
+        vm.set_depth_profile(Box::new(BufWriter::new(file)), interval)
+            .unwrap();
+    }
+
+    if let Some(text) = options.input {
+        vm.queue_input(&text);
+    }
+
+    if let Some(path) = options.script {
+        let file = File::open(path).expect("failed to open input script");
+        vm.add_input_script(Box::new(BufReader::new(file)));
+    }
+
+    vm.set_blank_line_policy(options.blank_line_policy);
+    vm.set_soft_halt(options.soft_halt);
+    vm.set_echo_input(options.echo_input);
+    vm.set_warn_uninit(options.warn_uninit);
+    vm.set_stop_at_input(options.banner);
+    vm.set_break_on_code_write(options.break_on_code_write);
+    vm.set_report_self_modify(options.warn_self_modify);
+    vm.set_count_addresses(options.hot.is_some());
+    vm.set_count_opcodes(options.op_histogram);
+    vm.set_decode_cache(options.decode_cache);
+
+    if let Some((reg, value)) = options.stop_when {
+        vm.break_on_register(reg, value);
+    }
+
+    if options.debug {
+        debug(&mut vm).unwrap();
+        return;
+    }
+
+    #[cfg(all(feature = "interrupt", unix))]
+    vm.set_interrupt_flag(synacor::install_interrupt_handler());
+
+    let (result, run_time) = timed(|| vm.run());
+    let interrupted = matches!(result, Ok(HaltReason::Interrupted));
+    if options.time {
+        eprintln!("load: {load_time:?}, run: {run_time:?}");
+    }
+    if options.stats {
+        eprintln!("cycles: {}", vm.cycles());
+        eprintln!("coverage: {:.2}%", vm.coverage_ratio() * 100.0);
+    }
+    if options.dump_state || interrupted {
+        for (reg, value) in vm.registers().iter().enumerate() {
+            eprintln!("r{reg} = {value}");
+        }
+        eprintln!("stack ({}): {:?}", vm.stack().len(), vm.stack());
+        eprintln!("pc: {}", vm.pc());
+        eprintln!("cycles: {}", vm.cycles());
+    }
+    if options.op_histogram {
+        for (mnemonic, runs) in vm.opcode_histogram() {
+            eprintln!("{mnemonic}: {runs}");
+        }
+    }
+    if let Some(count) = options.hot {
+        for (addr, runs) in vm.hot_addresses().into_iter().take(count) {
+            eprintln!("{addr}: {runs}");
+        }
+    }
+    let mut save_path = options.save_state;
+    if interrupted && save_path.is_none() {
+        eprint!("save state to (blank to skip): ");
+        let mut line = String::new();
+        io::stdin().read_line(&mut line).unwrap();
+        save_path = Some(line.trim().to_string()).filter(|path| !path.is_empty());
+    }
+    if let Some(path) = &save_path {
+        vm.save_state(Path::new(path)).unwrap();
+    }
+    eprintln!("run ended: {:?}", result.unwrap());
 }
@@ -0,0 +1,13 @@
+use crate::{Instruction, VM};
+
+/// Hooks the VM calls around every instruction `step` executes, for tools
+/// that want to watch execution without the VM knowing about them.
+pub trait VmObserver {
+    /// Called once `inst` is decoded, before it runs. Its address is
+    /// `vm.current_pc()`. An `in` that finds no input stops the step here,
+    /// without `after_step`.
+    fn before_step(&mut self, vm: &VM, inst: &Instruction);
+
+    /// Called after the instruction has run.
+    fn after_step(&mut self, vm: &VM);
+}
@@ -0,0 +1,128 @@
+//! Solvers for the puzzles the challenge poses in-game. They work on the
+//! puzzle as described in the game text and don't touch the VM.
+
+use std::collections::{HashSet, VecDeque};
+
+/// The weight the orb must have when it reaches the vault door.
+pub const VAULT_WEIGHT: i32 = 30;
+
+/// Orb weights outside `0..MAX_ORB_WEIGHT` are dropped from the search, which
+/// keeps it finite.
+const MAX_ORB_WEIGHT: i32 = 1 << 15;
+
+/// Finds the order to place the coins worth `values` in so that
+/// `a + b * c^2 + d^3 - e = 399`, using five of them.
+pub fn solve_coins(values: &[u16]) -> Option<[u16; 5]> {
+    fn search(values: &[u16], order: &mut Vec<u16>, used: &mut [bool]) -> Option<[u16; 5]> {
+        if let Ok(coins) = <[u16; 5]>::try_from(&order[..]) {
+            let [a, b, c, d, e] = coins.map(i64::from);
+            return (a + b * c.pow(2) + d.pow(3) - e == 399).then_some(coins);
+        }
+        for i in 0..values.len() {
+            if used[i] {
+                continue;
+            }
+            used[i] = true;
+            order.push(values[i]);
+            if let Some(coins) = search(values, order, used) {
+                return Some(coins);
+            }
+            order.pop();
+            used[i] = false;
+        }
+        None
+    }
+
+    search(values, &mut Vec::new(), &mut vec![false; values.len()])
+}
+
+/// A room of the orb grid: a number, or an operator applied to the next
+/// number the orb is carried onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OrbCell {
+    Number(u16),
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Direction {
+    const ALL: [Self; 4] = [Self::North, Self::South, Self::East, Self::West];
+
+    /// The room one step this way from `(row, col)` on a 4x4 grid, if any.
+    fn step(self, (row, col): (usize, usize)) -> Option<(usize, usize)> {
+        let (row, col) = match self {
+            Self::North => (row.checked_sub(1)?, col),
+            Self::South => (row + 1, col),
+            Self::East => (row, col + 1),
+            Self::West => (row, col.checked_sub(1)?),
+        };
+        (row < 4 && col < 4).then_some((row, col))
+    }
+}
+
+/// Finds the shortest walk carrying the orb from the bottom-left room to the
+/// vault door at the top-right so it weighs `VAULT_WEIGHT` on arrival. Row 0
+/// of `grid` is the northernmost. The orb starts out weighing the start
+/// room's number, can't re-enter the start room, and the walk ends as soon
+/// as it reaches the vault.
+pub fn solve_orb_grid(grid: &[[OrbCell; 4]; 4]) -> Option<Vec<Direction>> {
+    let start = (3, 0);
+    let vault = (0, 3);
+    let OrbCell::Number(weight) = grid[start.0][start.1] else {
+        return None;
+    };
+
+    let mut queue = VecDeque::from([(start, i32::from(weight), None, Vec::new())]);
+    let mut seen = HashSet::new();
+
+    while let Some((room, weight, pending, path)) = queue.pop_front() {
+        for direction in Direction::ALL {
+            let Some(next) = direction.step(room) else {
+                continue;
+            };
+            if next == start {
+                continue;
+            }
+
+            let (weight, pending) = match (grid[next.0][next.1], pending) {
+                (OrbCell::Number(value), Some(op)) => {
+                    let value = i32::from(value);
+                    let weight = match op {
+                        OrbCell::Add => weight + value,
+                        OrbCell::Sub => weight - value,
+                        _ => weight * value,
+                    };
+                    (weight, None)
+                }
+                (OrbCell::Number(_), None) => continue,
+                (op, _) => (weight, Some(op)),
+            };
+            if !(0..MAX_ORB_WEIGHT).contains(&weight) {
+                continue;
+            }
+
+            let mut path = path.clone();
+            path.push(direction);
+            if next == vault {
+                if weight == VAULT_WEIGHT && pending.is_none() {
+                    return Some(path);
+                }
+                continue;
+            }
+            if seen.insert((next, weight, pending)) {
+                queue.push_back((next, weight, pending, path));
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{VmError, MEMORY_SIZE, VM};
+
+/// The first bytes of every state file.
+const STATE_MAGIC: &[u8; 4] = b"SYNS";
+
+/// Bumped whenever the layout after the header changes.
+const STATE_VERSION: u8 = 1;
+
+/// Reads back the little-endian fields written by `VmState::encode`.
+struct StateReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl StateReader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], VmError> {
+        let (head, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or(VmError::InvalidState("truncated"))?;
+        self.bytes = rest;
+        Ok(*head)
+    }
+
+    fn u16(&mut self) -> Result<u16, VmError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, VmError> {
+        self.take().map(u32::from_le_bytes)
+    }
+}
+
+/// Everything a state file holds: registers, `pc`, stack and memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmState {
+    pub registers: [u16; 8],
+    pub pc: usize,
+    /// Bottom first.
+    pub stack: Vec<u16>,
+    /// All `MEMORY_SIZE` words.
+    pub memory: Vec<u16>,
+}
+
+impl VmState {
+    /// Encodes the state as:
+    ///
+    /// - the magic `SYNS` and a version byte,
+    /// - the eight registers as `u16`s,
+    /// - `pc` as a `u32`,
+    /// - the stack length as a `u32`, then its entries as `u16`s, bottom first,
+    /// - memory as `(count: u16, word: u16)` runs covering every address.
+    ///
+    /// Every value is little-endian.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = STATE_MAGIC.to_vec();
+        bytes.push(STATE_VERSION);
+        for reg in self.registers {
+            bytes.extend(reg.to_le_bytes());
+        }
+        bytes.extend((self.pc as u32).to_le_bytes());
+        bytes.extend((self.stack.len() as u32).to_le_bytes());
+        for value in &self.stack {
+            bytes.extend(value.to_le_bytes());
+        }
+
+        for run in self.memory.chunk_by(|a, b| a == b) {
+            bytes.extend((run.len() as u16).to_le_bytes());
+            bytes.extend(run[0].to_le_bytes());
+        }
+
+        bytes
+    }
+
+    /// Parses bytes written by `encode`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, VmError> {
+        let mut reader = StateReader { bytes };
+
+        if reader.take::<4>().ok().as_ref() != Some(STATE_MAGIC) {
+            return Err(VmError::InvalidState("not a state file"));
+        }
+        let [version] = reader.take()?;
+        if version != STATE_VERSION {
+            return Err(VmError::UnsupportedStateVersion(version));
+        }
+
+        let mut registers = [0; 8];
+        for reg in &mut registers {
+            *reg = reader.u16()?;
+        }
+        let pc = reader.u32()? as usize;
+        let stack_len = reader.u32()? as usize;
+        let stack = (0..stack_len)
+            .map(|_| reader.u16())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut memory = Vec::with_capacity(MEMORY_SIZE);
+        while memory.len() < MEMORY_SIZE {
+            let count = reader.u16()? as usize;
+            let word = reader.u16()?;
+            if count == 0 || memory.len() + count > MEMORY_SIZE {
+                return Err(VmError::InvalidState("memory runs don't cover memory"));
+            }
+            memory.resize(memory.len() + count, word);
+        }
+        if !reader.bytes.is_empty() {
+            return Err(VmError::InvalidState("trailing data"));
+        }
+
+        Ok(Self {
+            registers,
+            pc,
+            stack,
+            memory,
+        })
+    }
+
+    /// Reads a state file written by `VM::save_state`.
+    pub fn read(path: &Path) -> Result<Self, VmError> {
+        Self::decode(&fs::read(path)?)
+    }
+}
+
+/// How two states differ, as `(old, new)` pairs going from the first to the
+/// second.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// `(reg, old, new)` for every register that differs.
+    pub registers: Vec<(usize, u16, u16)>,
+    /// `(addr, old, new)` for every memory cell that differs, in address
+    /// order.
+    pub memory: Vec<(usize, u16, u16)>,
+    pub pc: Option<(usize, usize)>,
+    /// Both stacks, if they differ at all.
+    pub stack: Option<(Vec<u16>, Vec<u16>)>,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Compares two states field by field.
+pub fn diff_states(a: &VmState, b: &VmState) -> StateDiff {
+    let changed =
+        |(index, (&old, &new)): (usize, (&u16, &u16))| (old != new).then_some((index, old, new));
+
+    StateDiff {
+        registers: a
+            .registers
+            .iter()
+            .zip(&b.registers)
+            .enumerate()
+            .filter_map(changed)
+            .collect(),
+        memory: a
+            .memory
+            .iter()
+            .zip(&b.memory)
+            .enumerate()
+            .filter_map(changed)
+            .collect(),
+        pc: (a.pc != b.pc).then_some((a.pc, b.pc)),
+        stack: (a.stack != b.stack).then(|| (a.stack.clone(), b.stack.clone())),
+    }
+}
+
+impl VM {
+    /// A copy of the registers, `pc`, stack and memory.
+    pub fn state(&self) -> VmState {
+        VmState {
+            registers: self.registers,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            memory: self.memory.to_vec(),
+        }
+    }
+
+    /// Encodes the current state in the format described at `VmState::encode`.
+    pub fn encode_state(&self) -> Vec<u8> {
+        self.state().encode()
+    }
+
+    /// Restores the state from bytes written by `encode_state`, leaving the
+    /// VM untouched if they are malformed. Call frames, rewind history and
+    /// any input left over from a partly read line belong to the state being
+    /// replaced, so they are dropped.
+    pub fn decode_state(&mut self, bytes: &[u8]) -> Result<(), VmError> {
+        let state = VmState::decode(bytes)?;
+
+        self.memory.copy_from_slice(&state.memory);
+        self.clear_decoded();
+        self.registers = state.registers;
+        self.stack = state.stack;
+        self.pc = state.pc;
+        self.call_frames.clear();
+        self.history.clear();
+        self.input_line.clear();
+        self.input_pos = 0;
+        self.forget_states();
+
+        Ok(())
+    }
+
+    /// Writes the state to `path` in the `encode_state` format.
+    pub fn save_state(&self, path: &Path) -> Result<(), VmError> {
+        Ok(fs::write(path, self.encode_state())?)
+    }
+
+    /// Restores a state file written by `save_state`.
+    pub fn load_state(&mut self, path: &Path) -> Result<(), VmError> {
+        self.decode_state(&fs::read(path)?)
+    }
+}
@@ -0,0 +1,84 @@
+//! A VM wrapper for browser embedders, behind the `wasm` feature. Input and
+//! output only ever go through in-memory buffers, so nothing here touches
+//! stdin or stdout, and every method takes and returns plain values that
+//! cross a JS boundary easily.
+
+use std::io;
+
+use crate::{HaltReason, SharedBuffer, StepResult, VM};
+
+/// `step` executed an instruction and the program can carry on.
+pub const STATUS_RUNNING: u8 = 0;
+/// The program executed `halt`.
+pub const STATUS_HALTED: u8 = 1;
+/// The program is waiting for `push_input`.
+pub const STATUS_NEEDS_INPUT: u8 = 2;
+/// The program stopped for any other reason.
+pub const STATUS_STOPPED: u8 = 3;
+/// The VM hit an error and can't continue.
+pub const STATUS_ERROR: u8 = 4;
+
+pub struct WasmVm {
+    vm: VM,
+    output: SharedBuffer,
+}
+
+impl Default for WasmVm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasmVm {
+    pub fn new() -> Self {
+        let output = SharedBuffer::new();
+        let vm = VM::builder()
+            .input(Box::new(io::empty()))
+            .output(Box::new(output.clone()))
+            .build();
+        Self { vm, output }
+    }
+
+    /// Loads a little-endian program image, returning whether it fit.
+    pub fn load(&mut self, bytes: &[u8]) -> bool {
+        self.vm.load(bytes).is_ok()
+    }
+
+    /// Executes one instruction and returns one of the `STATUS_*` codes.
+    pub fn step(&mut self) -> u8 {
+        match self.vm.step() {
+            Ok(StepResult::Stopped(HaltReason::Halted)) => STATUS_HALTED,
+            Ok(StepResult::Stopped(HaltReason::OutOfInput)) => STATUS_NEEDS_INPUT,
+            Ok(StepResult::Stopped(_)) => STATUS_STOPPED,
+            Ok(_) => STATUS_RUNNING,
+            Err(_) => STATUS_ERROR,
+        }
+    }
+
+    /// Steps up to `max_steps` times, stopping early on anything but
+    /// `STATUS_RUNNING`, and returns the last status.
+    pub fn run(&mut self, max_steps: u32) -> u8 {
+        let mut status = STATUS_RUNNING;
+        for _ in 0..max_steps {
+            status = self.step();
+            if status != STATUS_RUNNING {
+                break;
+            }
+        }
+        status
+    }
+
+    pub fn registers(&self) -> Vec<u16> {
+        self.vm.registers().to_vec()
+    }
+
+    /// Queues bytes for the program's `in` instructions.
+    pub fn push_input(&mut self, bytes: &[u8]) {
+        self.vm.queue_input_bytes(bytes);
+    }
+
+    /// Returns the bytes printed since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.output.take()
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+
+use synacor::{assemble, find_refs, RefTarget, VmError, VM};
+
+/// The address of the teleporter's confirmation routine in `challenge.bin`.
+const CONFIRMATION_ROUTINE: usize = 6027;
+
+fn challenge() -> VM {
+    let program = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/challenge.bin")).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program).unwrap();
+    vm
+}
+
+#[test]
+fn finds_the_confirmation_routine() {
+    assert!(challenge()
+        .confirmation_candidates()
+        .contains(&CONFIRMATION_ROUTINE));
+}
+
+#[test]
+fn checksum_is_stable_and_tracks_memory() {
+    let mut vm = challenge();
+    let checksum = vm.program_checksum();
+    assert_eq!(challenge().program_checksum(), checksum);
+
+    vm.poke_mem(100, vm.memory()[100] ^ 1).unwrap();
+    assert_ne!(vm.program_checksum(), checksum);
+}
+
+/// 0: set r1 5, 3: add r0 r1 1, 7: wmem 100 r0, 10: rmem r2 100,
+/// 13: rmem r3 r1, 16: out r2, 18: halt
+const REFS: &str = "set r1 5\nadd r0 r1 1\nwmem 100 r0\nrmem r2 100\nrmem r3 r1\nout r2\nhalt";
+
+fn refs(target: RefTarget) -> Vec<usize> {
+    let mut vm = VM::new();
+    vm.load(&assemble(REFS).unwrap()).unwrap();
+    find_refs(vm.memory(), target)
+}
+
+#[test]
+fn find_refs_reports_reads_and_writes_of_a_register() {
+    assert_eq!(refs(RefTarget::Register(1)), [0, 3, 13]);
+    assert_eq!(refs(RefTarget::Register(2)), [10, 16]);
+}
+
+#[test]
+fn find_refs_includes_dynamic_memory_accesses() {
+    assert_eq!(refs(RefTarget::Address(100)), [7, 10, 13]);
+    assert_eq!(refs(RefTarget::Address(200)), [13]);
+}
+
+#[test]
+fn search_finds_every_match_in_order() {
+    let mut vm = VM::new();
+    vm.load_words(&[1, 2, 3, 9, 1, 2, 3]).unwrap();
+    vm.load_at(&[1, 0, 2, 0, 3, 0], 500).unwrap();
+    assert_eq!(vm.search(&[1, 2, 3]), [0, 4, 500]);
+    assert!(vm.search(&[]).is_empty());
+}
+
+#[test]
+fn search_reports_overlapping_matches() {
+    let mut vm = VM::new();
+    vm.load_words(&[7, 7, 7, 1]).unwrap();
+    assert_eq!(vm.search(&[7, 7]), [0, 1]);
+}
+
+#[test]
+fn verify_reports_a_bad_opcode_on_a_reachable_branch() {
+    // 0: jt r0 5, 3: halt, then a bad opcode at 4 that nothing reaches and
+    // one at the jump target 5.
+    let mut vm = VM::new();
+    vm.load_words(&[7, 32768, 5, 0, 99, 99]).unwrap();
+
+    let errors = vm.verify().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(matches!(
+        &errors[0],
+        VmError::DecodeFailed { addr: 5, cause } if matches!(**cause, VmError::UnknownOpcode(99))
+    ));
+
+    vm.poke_mem(5, 0).unwrap();
+    assert!(vm.verify().is_ok());
+}
+
+#[test]
+fn dump_memory_formats_rows_and_clamps_to_memory() {
+    let mut vm = VM::new();
+    vm.load_words(&[72, 105, 0, 0x1234, 32, 126, 256, 65, 66, 67])
+        .unwrap();
+    assert_eq!(
+        vm.dump_memory(0, 10),
+        "0000: 0048 0069 0000 1234 0020 007e 0100 0041  |Hi.. ~.A|\n\
+         0008: 0042 0043                                |BC|\n"
+    );
+
+    vm.poke_mem(32767, 90).unwrap();
+    assert_eq!(
+        vm.dump_memory(32766, 10),
+        format!("7ffe: 0000 005a{}  |.Z|\n", " ".repeat(30))
+    );
+    assert_eq!(vm.dump_memory(40000, 10), "");
+}
+
+#[test]
+fn one_branch_splits_the_code_into_three_blocks() {
+    // 0: jt r0 5, 3: out 65, 5: halt
+    let mut vm = VM::new();
+    vm.load(&assemble("jt r0 5\nout 65\nhalt").unwrap())
+        .unwrap();
+
+    let cfg = vm.control_flow_graph(0);
+    assert_eq!(cfg.blocks, [(0, 3), (3, 5), (5, 6)]);
+    assert_eq!(cfg.edges, [(0, 5), (0, 3), (3, 5)]);
+    assert!(cfg.to_dot().contains("\"0\" -> \"5\";"));
+}
+
+#[test]
+fn dispatch_targets_reads_the_jump_table() {
+    // 0: add r1 r0 20, 4: rmem r2 r1, 7: jmp r2, 9: out 65, 11: halt, and a
+    // table at 20 whose last entry isn't an address.
+    let mut vm = VM::new();
+    vm.load(&assemble("add r1 r0 20\nrmem r2 r1\njmp r2\nout 65\nhalt").unwrap())
+        .unwrap();
+    vm.load_at(&[9, 0, 11, 0, 0x40, 0x9c], 20).unwrap();
+
+    assert_eq!(vm.dispatch_targets(0).unwrap(), [9, 11]);
+    assert!(matches!(
+        vm.dispatch_targets(9),
+        Err(VmError::NoDispatch { addr: 9 })
+    ));
+}
+
+#[test]
+fn out_literal_runs_recover_printed_strings() {
+    // 0: out 65, 2: out 66, 4: out 67, 6: set r0 1, 9: out 68, 11: out r0
+    let mut vm = VM::new();
+    vm.load(&assemble("out 65\nout 66\nout 67\nset r0 1\nout 68\nout r0\nhalt").unwrap())
+        .unwrap();
+
+    assert_eq!(vm.out_literal_runs(3), [(0, "ABC".to_string())]);
+    assert_eq!(
+        vm.out_literal_runs(1),
+        [(0, "ABC".to_string()), (9, "D".to_string())]
+    );
+}
+
+#[test]
+fn dump_nonzero_prints_only_the_loaded_regions() {
+    let mut vm = VM::new();
+    vm.load_words(&[19, 65, 21, 21]).unwrap();
+    assert_eq!(vm.dump_nonzero(), "0..4: 0013 0041 0015 0015\n");
+
+    vm.poke_mem(32767, 0xbeef).unwrap();
+    assert_eq!(
+        vm.dump_nonzero(),
+        "0..4: 0013 0041 0015 0015\n32767..32768: beef\n"
+    );
+}
+
+#[test]
+fn code_extent_stops_at_the_first_word_that_does_not_decode() {
+    // out 65, add r0 r0 1, halt, then data.
+    let mut vm = VM::new();
+    vm.load_words(&[19, 65, 9, 32768, 32768, 1, 0, 1000, 19, 66])
+        .unwrap();
+    assert_eq!(vm.code_extent(), (3, 7));
+}
+
+#[test]
+fn out_literal_runs_count_characters_not_bytes() {
+    // Two `out`s past ASCII print two characters that take four bytes.
+    let mut vm = VM::new();
+    vm.load(&assemble("out 200\nout 201\nhalt").unwrap())
+        .unwrap();
+    assert!(vm.out_literal_runs(3).is_empty());
+    assert_eq!(vm.out_literal_runs(2), [(0, "ÈÉ".to_string())]);
+}
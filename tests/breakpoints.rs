@@ -0,0 +1,171 @@
+use synacor::{assemble, HaltReason, SharedBuffer, StepResult, VmError, WatchLocation, VM};
+
+/// Counts r0 from 1 to 10, passing address 7 once per iteration.
+const COUNTING_LOOP: &str = "set r0 0\nadd r0 r0 1\neq r1 r0 10\njf r1 3\nhalt";
+
+#[test]
+fn conditional_breakpoint_stops_only_when_it_holds() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTING_LOOP).unwrap()).unwrap();
+    vm.add_conditional_breakpoint(7, "r0==5").unwrap();
+
+    assert_eq!(
+        vm.run_until_break().unwrap(),
+        HaltReason::Breakpoint { addr: 7 }
+    );
+    assert_eq!(vm.registers()[0], 5);
+    assert_eq!(vm.run_until_break().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 10);
+}
+
+#[test]
+fn invalid_conditions_are_rejected() {
+    let mut vm = VM::new();
+    assert!(vm.add_conditional_breakpoint(7, "r0==").is_err());
+}
+
+#[test]
+fn a_condition_reading_past_memory_stops_instead_of_panicking() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("rmem r0 6\nhalt").unwrap()).unwrap();
+    vm.poke_mem(6, 40000).unwrap();
+    vm.add_conditional_breakpoint(3, "mem[r0]").unwrap();
+
+    assert_eq!(
+        vm.run_until_break().unwrap(),
+        HaltReason::Breakpoint { addr: 3 }
+    );
+    assert_eq!(vm.registers()[0], 40000);
+}
+
+#[test]
+fn step_over_runs_a_whole_call() {
+    // 0: call 6, 2: set r1 2, 5: halt, 6: set r0 7, 9: push 9, 11: pop r2, 13: ret
+    let source = "call 6\nset r1 2\nhalt\nset r0 7\npush 9\npop r2\nret";
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(source).unwrap()).unwrap();
+
+    assert_eq!(vm.step_over().unwrap(), StepResult::Continue);
+    assert_eq!(vm.pc(), 2);
+    assert_eq!(vm.registers()[..3], [7, 0, 9]);
+    assert!(vm.stack().is_empty());
+
+    vm.step_over().unwrap();
+    assert_eq!(vm.pc(), 5);
+    assert_eq!(vm.registers()[1], 2);
+}
+
+#[test]
+fn step_over_a_call_that_never_returns_hits_the_cycle_limit() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("call 2\njmp 2").unwrap()).unwrap();
+    vm.set_max_cycles(1000);
+    assert!(matches!(
+        vm.step_over(),
+        Err(VmError::CycleLimitExceeded(1000))
+    ));
+}
+
+#[test]
+fn step_n_runs_exactly_n_instructions() {
+    // The fifth instruction, `set r1 2`, ends at 9.
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("noop\nnoop\nset r0 1\nnoop\nset r1 2\nnoop\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.step_n(5).unwrap(), (StepResult::Continue, 5));
+    assert_eq!(vm.pc(), 9);
+    assert_eq!(vm.cycles(), 5);
+}
+
+#[test]
+fn step_n_stops_early_at_halt() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("noop\nhalt").unwrap()).unwrap();
+    assert_eq!(
+        vm.step_n(10).unwrap(),
+        (StepResult::Stopped(HaltReason::Halted), 2)
+    );
+}
+
+#[test]
+fn a_register_watch_reports_the_old_and_new_value() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("add r0 1 1\nhalt").unwrap()).unwrap();
+    vm.add_reg_watch(0);
+
+    assert_eq!(
+        vm.step().unwrap(),
+        StepResult::Watchpoint {
+            location: WatchLocation::Register(0),
+            old: 0,
+            new: 2,
+        }
+    );
+}
+
+#[test]
+fn writing_over_executed_code_reports_self_modify() {
+    // 0: noop, 1: wmem 100 5, 4: wmem 0 0, 7: halt
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("noop\nwmem 100 5\nwmem 0 0\nhalt").unwrap())
+        .unwrap();
+    vm.set_report_self_modify(true);
+
+    assert_eq!(vm.step().unwrap(), StepResult::Continue);
+    assert_eq!(vm.step().unwrap(), StepResult::Continue);
+    assert_eq!(
+        vm.step().unwrap(),
+        StepResult::SelfModify {
+            addr: 0,
+            old: 21,
+            new: 0,
+        }
+    );
+}
+
+#[test]
+fn the_run_stops_at_the_write_of_the_target_value() {
+    // 0: set r0 40, 3: add r0 r0 1, 7: add r0 r0 1, 11: add r0 r0 1
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("set r0 40\nadd r0 r0 1\nadd r0 r0 1\nadd r0 r0 1\nhalt").unwrap())
+        .unwrap();
+    vm.break_on_register(0, 42);
+
+    assert_eq!(vm.run().unwrap(), HaltReason::RegisterMatch { addr: 7 });
+    assert_eq!(vm.registers()[0], 42);
+    assert_eq!(vm.pc(), 11);
+}
+
+#[test]
+fn break_on_code_write_stops_at_the_overwrite() {
+    // 0: noop, 1: wmem 100 5, 4: wmem 2 0, 7: halt
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("noop\nwmem 100 5\nwmem 2 0\nhalt").unwrap())
+        .unwrap();
+    vm.set_break_on_code_write(true);
+
+    assert_eq!(
+        vm.run().unwrap(),
+        HaltReason::CodeWrite { addr: 4, target: 2 }
+    );
+    assert_eq!(vm.memory()[100], 5);
+    assert_eq!(vm.pc(), 7);
+}
+
+#[test]
+fn a_breakpoint_stops_before_its_instruction_runs() {
+    // 0: set r0 5, 3: set r0 9, 6: halt
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("set r0 5\nset r0 9\nhalt").unwrap())
+        .unwrap();
+    vm.add_breakpoint(3);
+
+    assert_eq!(
+        vm.run_until_break().unwrap(),
+        HaltReason::Breakpoint { addr: 3 }
+    );
+    assert_eq!(vm.pc(), 3);
+    assert_eq!(vm.registers()[0], 5);
+    assert_eq!(vm.run_until_break().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 9);
+}
@@ -0,0 +1,145 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::{env, fs};
+
+/// Runs the binary on `words` with `args`, feeding it `stdin`.
+fn run(name: &str, words: &[u16], args: &[&str], stdin: &[u8]) -> Output {
+    let path = env::temp_dir().join(format!("synacor-{name}-{}.bin", std::process::id()));
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    fs::write(&path, bytes).unwrap();
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_synacor"))
+        .arg(&path)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    let output = child.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    output
+}
+
+#[test]
+fn dump_state_runs_to_completion() {
+    // set r0 7, push 9, halt
+    let output = run(
+        "dump-state",
+        &[1, 32768, 7, 2, 9, 0],
+        &["--dump-state"],
+        b"",
+    );
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("r0 = 7"), "{stderr}");
+    assert!(stderr.contains("stack (1): [9]"), "{stderr}");
+}
+
+#[test]
+fn debugger_history_shows_the_last_instructions() {
+    // 0: noop, 1: set r0 7, 4: push 9, 6: noop, 7: halt
+    let output = run(
+        "history",
+        &[21, 1, 32768, 7, 2, 9, 21, 0],
+        &["--debug"],
+        b"s\ns\ns\ns\nhistory 3\nq\n",
+    );
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("0001: set r0 7\n0004: push 9\n0006: noop\n"),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn debugger_asm_patches_code() {
+    // 0: out 65, 2: halt
+    let output = run("asm", &[19, 65, 0], &["--debug"], b"asm 0 \"out 66\"\nc\n");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("0000: out 66"), "{stdout}");
+    assert!(stdout.contains("B"), "{stdout}");
+}
+
+#[test]
+fn bad_fault_flags_are_usage_errors() {
+    for args in [
+        &["--fault-addr", "5"][..],
+        &["--fault-bit", "3"],
+        &["--fault-addr", "40000", "--fault-bit", "0"],
+        &["--fault-addr", "5", "--fault-bit", "16"],
+        &["--fault-cycle", "10"],
+    ] {
+        let output = run("bad-fault", &[0], args, b"");
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(!output.stderr.is_empty());
+    }
+}
+
+#[test]
+fn literals_lists_operands_and_rejects_unknown_mnemonics() {
+    // out 65, out 66, halt
+    let output = run(
+        "literals",
+        &[19, 65, 19, 66, 0],
+        &["--literals", "out"],
+        b"",
+    );
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1: 65\n3: 66\n");
+
+    let output = run("bad-literals", &[0], &["--literals", "bogus"], b"");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("bogus"));
+}
+
+#[test]
+fn warn_uninit_reports_reads_of_never_written_memory() {
+    // 0: rmem r0 20000, 3: wmem 20001 5, 6: rmem r1 20001, 9: halt
+    let output = run(
+        "warn-uninit",
+        &[15, 32768, 20000, 16, 20001, 5, 15, 32769, 20001, 0],
+        &["--warn-uninit"],
+        b"",
+    );
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("read of uninitialized memory at 20000 (pc 0)"),
+        "{stderr}"
+    );
+    assert!(!stderr.contains("at 20001"), "{stderr}");
+}
+
+#[test]
+fn banner_prints_only_the_output_before_the_first_input() {
+    // out 72, out 105, out 10, in r0, out r0, halt
+    let output = run(
+        "banner",
+        &[19, 72, 19, 105, 19, 10, 20, 32768, 19, 32768, 0],
+        &["--banner"],
+        b"x\n",
+    );
+
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"Hi\n");
+}
+
+#[test]
+fn bad_time_limits_are_usage_errors() {
+    for value in ["-1", "nan", "inf"] {
+        let output = run("bad-time-limit", &[0], &["--time-limit", value], b"");
+        assert_eq!(output.status.code(), Some(2), "{value}");
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--time-limit"));
+    }
+
+    let output = run("time-limit", &[0], &["--time-limit", "5"], b"");
+    assert!(output.status.success());
+}
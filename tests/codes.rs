@@ -0,0 +1,29 @@
+use std::fs;
+
+use synacor::codes::scan_binary_for_codes;
+use synacor::VM;
+
+#[test]
+fn finds_the_code_in_the_challenge_binary() {
+    let program = fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/challenge.bin")).unwrap();
+    let mut vm = VM::new();
+    vm.load(&program).unwrap();
+    assert!(scan_binary_for_codes(vm.memory()).contains(&"KnhvAesVjFus".to_string()));
+}
+
+#[test]
+fn finds_printed_and_stored_codes_but_not_words() {
+    // One code printed with `out` literals, then a halt, then text holding
+    // a second code next to an ordinary capitalized word.
+    let mut image: Vec<u16> = "AbCdEfGhIjKl"
+        .bytes()
+        .flat_map(|byte| [19, u16::from(byte)])
+        .collect();
+    image.push(0);
+    image.extend("Code: mQ3pLx9Tz2Wa\nConstitution\n".bytes().map(u16::from));
+
+    assert_eq!(
+        scan_binary_for_codes(&image),
+        ["mQ3pLx9Tz2Wa", "AbCdEfGhIjKl"]
+    );
+}
@@ -0,0 +1,22 @@
+//! Fixtures shared by the integration tests. Not every test file uses every
+//! helper.
+#![allow(dead_code)]
+
+use synacor::{assemble, SharedBuffer, VM};
+
+/// A VM with `source` loaded and output captured in the returned buffer.
+/// Its only input is what the test queues, so it never reads stdin.
+pub fn load(source: &str) -> (VM, SharedBuffer) {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(b"")
+        .output(Box::new(output.clone()))
+        .build();
+    vm.load(&assemble(source).unwrap()).unwrap();
+    (vm, output)
+}
+
+/// Like `load`, for tests that don't look at the output.
+pub fn vm(source: &str) -> VM {
+    load(source).0
+}
@@ -0,0 +1,60 @@
+mod common;
+
+use synacor::{HaltReason, SharedBuffer, UnknownOpPolicy, VmError, VM};
+
+fn vm(source: &str, cache: bool) -> (VM, SharedBuffer) {
+    let (mut vm, output) = common::load(source);
+    vm.set_decode_cache(cache);
+    (vm, output)
+}
+
+#[test]
+fn the_cache_does_not_change_what_runs() {
+    let source = "set r0 0\nadd r0 r0 1\nout 46\neq r1 r0 1000\njf r1 3\nhalt";
+    let (mut plain, plain_output) = vm(source, false);
+    let (mut cached, cached_output) = vm(source, true);
+
+    assert_eq!(plain.run().unwrap(), HaltReason::Halted);
+    assert_eq!(cached.run().unwrap(), HaltReason::Halted);
+    assert_eq!(cached.cycles(), plain.cycles());
+    assert_eq!(cached.registers(), plain.registers());
+    assert_eq!(cached_output.contents(), plain_output.contents());
+}
+
+#[test]
+fn a_wmem_into_cached_code_runs_the_new_instruction() {
+    // 0: out 65, 2: wmem 1 66, 5: add r0 r0 1, 9: eq r1 r0 2, 13: jf r1 0, 16: halt
+    let (mut vm, output) = vm(
+        "out 65\nwmem 1 66\nadd r0 r0 1\neq r1 r0 2\njf r1 0\nhalt",
+        true,
+    );
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b"AB");
+}
+
+#[test]
+fn changing_the_unknown_op_policy_drops_cached_noops() {
+    // 0: an unknown opcode, 1: jmp 0
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load_words(&[9999, 6, 0]).unwrap();
+    vm.set_decode_cache(true);
+    vm.set_unknown_op_policy(UnknownOpPolicy::Noop);
+    vm.step().unwrap();
+    vm.step().unwrap();
+
+    vm.set_unknown_op_policy(UnknownOpPolicy::Abort);
+    assert!(matches!(vm.step(), Err(VmError::UnknownOpcode(9999))));
+}
+
+#[test]
+fn turning_on_strict_decoding_drops_cached_instructions() {
+    // 0: set 3 42, with a literal destination, 3: halt
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load_words(&[1, 3, 42, 0]).unwrap();
+    vm.set_decode_cache(true);
+    assert!(matches!(vm.step(), Err(VmError::InvalidInstruction { .. })));
+
+    vm.reset();
+    vm.set_strict_decode(true);
+    assert!(matches!(vm.step(), Err(VmError::ExpectedRegister { .. })));
+}
@@ -0,0 +1,40 @@
+use synacor::{assemble, decompile_routine};
+
+fn words(source: &str) -> Vec<u16> {
+    assemble(source)
+        .unwrap()
+        .chunks(2)
+        .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+#[test]
+fn decompiles_an_add_and_conditional_return() {
+    // 0: add, 4: eq, 8: jf r1 12, 11: ret, 12: set, 15: ret
+    let memory = words("add r0 r0 1\neq r1 r0 5\njf r1 12\nret\nset r0 0\nret");
+    let text = decompile_routine(&memory, 0);
+
+    assert!(text.contains("r0 = (r0 + 1) % 32768;"), "{text}");
+    assert!(
+        text.contains("if (r1 != 0) {\n        return;\n    }"),
+        "{text}"
+    );
+    assert!(
+        text.trim_end().ends_with("r0 = 0;\n    return;\n}"),
+        "{text}"
+    );
+}
+
+#[test]
+fn labels_backward_jumps() {
+    // 0: add r0 r0 1, 4: jt r0 0, 7: ret
+    let text = decompile_routine(&words("add r0 r0 1\njt r0 0\nret"), 0);
+    assert!(text.contains("L_0:\n"), "{text}");
+    assert!(text.contains("if (r0 != 0) goto L_0;"), "{text}");
+}
+
+#[test]
+fn bails_out_on_undecodable_words() {
+    let text = decompile_routine(&[1, 32768, 5, 9999], 0);
+    assert!(text.contains("can't decode the word at 3"), "{text}");
+}
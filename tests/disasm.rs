@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use synacor::{assemble, Arg, Instruction, Op, SharedBuffer, VM};
+
+/// Each instruction of `source`, rendered with `Display`.
+fn rendered(source: &str) -> Vec<String> {
+    let program = assemble(source).unwrap();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&program).unwrap();
+
+    let mut lines = Vec::new();
+    let mut addr = 0;
+    while addr < program.len() / 2 {
+        let (inst, next) = vm.decode_at(addr).unwrap();
+        lines.push(inst.to_string());
+        addr = next;
+    }
+    lines
+}
+
+#[test]
+fn instructions_display_as_assembly() {
+    assert_eq!(
+        rendered("add r0 r1 2\nhalt\nwmem r7 32767\njt r3 10"),
+        ["add r0 r1 2", "halt", "wmem r7 32767", "jt r3 10"]
+    );
+}
+
+#[test]
+fn invalid_instruction_errors_show_the_instruction() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("set 3 42").unwrap()).unwrap();
+    assert_eq!(
+        vm.step().unwrap_err().to_string(),
+        "unable to handle `set 3 42` at 0"
+    );
+}
+
+#[test]
+fn patch_asm_replaces_an_instruction() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("add r0 r1 2\nhalt").unwrap()).unwrap();
+
+    vm.patch_asm(0, "add r0 r0 1").unwrap();
+    assert_eq!(vm.disassemble(0..5), ["0000: add r0 r0 1", "0004: halt"]);
+
+    vm.patch_asm(0, "out r0").unwrap();
+    assert_eq!(
+        vm.disassemble(0..5),
+        ["0000: out r0", "0002: noop", "0003: noop", "0004: halt"]
+    );
+}
+
+#[test]
+fn patch_asm_rejects_what_does_not_fit() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("out 65\nhalt").unwrap()).unwrap();
+
+    assert!(vm.patch_asm(0, "add r0 r0 1").is_err());
+    assert!(vm.patch_asm(0, "noop\nnoop").is_err());
+    assert!(vm.patch_asm(0, "bogus").is_err());
+    assert_eq!(vm.disassemble(0..3), ["0000: out 65", "0002: halt"]);
+}
+
+#[test]
+fn out_of_range_registers_still_display() {
+    let mut inst = Instruction::new(Op::Set);
+    inst.a = Some(Arg::Register(8));
+    inst.b = Some(Arg::Literal(1));
+    assert_eq!(inst.to_string(), "set r8 1");
+}
+
+#[test]
+fn instruction_length_counts_the_opcode_and_operands() {
+    let mut vm = VM::new();
+    vm.load(&assemble("halt\nout 65\nadd r0 r1 2").unwrap())
+        .unwrap();
+    let (halt, next) = vm.decode_at(0).unwrap();
+    assert_eq!((halt.len(), next), (1, 1));
+    let (out, next) = vm.decode_at(1).unwrap();
+    assert_eq!((out.len(), next), (2, 3));
+    let (add, next) = vm.decode_at(3).unwrap();
+    assert_eq!((add.len(), next), (4, 7));
+}
+
+#[test]
+fn labeled_disassembly_names_a_backward_jump_target() {
+    // 0: out 65, 2: call 6, 4: jmp 0, 6: ret
+    let mut vm = VM::new();
+    vm.load(&assemble("out 65\ncall 6\njmp 0\nret").unwrap())
+        .unwrap();
+    let symbols = HashMap::from([(6, "greet".to_string())]);
+
+    assert_eq!(
+        vm.disassemble_labeled(0..7, &symbols),
+        [
+            "L_0:",
+            "0000: out 65",
+            "0002: call greet",
+            "0004: jmp L_0",
+            "greet:",
+            "0006: ret",
+        ]
+    );
+}
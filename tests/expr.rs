@@ -0,0 +1,79 @@
+use synacor::VM;
+
+fn vm() -> VM {
+    let mut vm = VM::new();
+    vm.set_register(0, 41).unwrap();
+    vm.set_register(2, 100).unwrap();
+    vm.poke_mem(100, 7).unwrap();
+    vm
+}
+
+#[test]
+fn adds_to_a_register() {
+    assert_eq!(vm().eval_expr("r0+1"), Ok(42));
+}
+
+#[test]
+fn reads_memory() {
+    assert_eq!(vm().eval_expr("mem[100]"), Ok(7));
+    assert_eq!(vm().eval_expr("mem[r2] * 2"), Ok(14));
+}
+
+#[test]
+fn rejects_memory_past_the_end() {
+    let mut vm = vm();
+    vm.set_register(0, 40000).unwrap();
+    assert!(vm.eval_expr("mem[r0]").is_err());
+}
+
+#[test]
+fn wraps_and_respects_precedence() {
+    assert_eq!(vm().eval_expr("0 - 1"), Ok(32767));
+    assert_eq!(vm().eval_expr("1 + 2 * 3"), Ok(7));
+    assert_eq!(vm().eval_expr("(1 + 2) * 3 | 16 & 48"), Ok(25));
+}
+
+#[test]
+fn rejects_invalid_expressions() {
+    assert!(vm().eval_expr("r8").is_err());
+    assert!(vm().eval_expr("r0 +").is_err());
+    assert!(vm().eval_expr("mem[1").is_err());
+    assert!(vm().eval_expr("5 % 0").is_err());
+}
+
+#[test]
+fn comparisons_give_one_or_zero() {
+    assert_eq!(vm().eval_expr("r0 == 41"), Ok(1));
+    assert_eq!(vm().eval_expr("r0 != 41"), Ok(0));
+    assert_eq!(vm().eval_expr("r0 + 1 > 41"), Ok(1));
+    assert_eq!(vm().eval_expr("r0 < 41"), Ok(0));
+}
+
+#[test]
+fn register_aliases_read_their_register() {
+    let mut vm = vm();
+    vm.set_register_alias(0, "counter").unwrap();
+    assert_eq!(vm.eval_expr("counter+1"), Ok(42));
+    assert_eq!(vm.eval_expr("r0+1"), Ok(42));
+    assert_eq!(
+        vm.eval_expr("count+1"),
+        Err("unknown register alias: count".to_string())
+    );
+}
+
+#[test]
+fn register_aliases_must_not_shadow_anything() {
+    let mut vm = vm();
+    assert!(vm.set_register_alias(0, "r3").is_err());
+    assert!(vm.set_register_alias(0, "mem").is_err());
+    assert!(vm.set_register_alias(0, "2fast").is_err());
+    assert!(vm.set_register_alias(8, "extra").is_err());
+}
+
+#[test]
+fn disassembly_uses_register_aliases() {
+    let mut vm = vm();
+    vm.load_words(&[1, 32775, 5]).unwrap();
+    vm.set_register_alias(7, "teleport_key").unwrap();
+    assert_eq!(vm.disassemble(0..1), ["0000: set teleport_key 5"]);
+}
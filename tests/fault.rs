@@ -0,0 +1,63 @@
+use synacor::{assemble, Fault, HaltReason, SharedBuffer, VmError, VM};
+
+/// Prints `AB` with the `B` computed, so both the literal and the `add`
+/// operands are worth flipping.
+const PROGRAM: &str = "out 65\nadd r0 r0 66\nout r0\nhalt";
+
+fn run(fault: Option<Fault>) -> Vec<u8> {
+    let output = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(output.clone()));
+    vm.load(&assemble(PROGRAM).unwrap()).unwrap();
+    if let Some(fault) = fault {
+        vm.inject_fault(fault).unwrap();
+    }
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    output.take()
+}
+
+#[test]
+fn flipping_an_operand_bit_changes_the_output() {
+    assert_eq!(run(None), b"AB");
+    assert_eq!(
+        run(Some(Fault {
+            addr: 1,
+            bit: 0,
+            cycle: None,
+        })),
+        b"@B"
+    );
+}
+
+#[test]
+fn a_timed_fault_only_affects_what_runs_after_it() {
+    // The `out 65` has already run by cycle 1; the `add` operand hasn't.
+    assert_eq!(
+        run(Some(Fault {
+            addr: 1,
+            bit: 0,
+            cycle: Some(1),
+        })),
+        b"AB"
+    );
+    assert_eq!(
+        run(Some(Fault {
+            addr: 5,
+            bit: 1,
+            cycle: Some(1),
+        })),
+        b"A@"
+    );
+}
+
+#[test]
+fn faults_outside_a_word_are_rejected() {
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.inject_fault(Fault {
+            addr: 0,
+            bit: 16,
+            cycle: None,
+        }),
+        Err(VmError::InvalidFaultBit(16))
+    ));
+}
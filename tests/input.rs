@@ -0,0 +1,178 @@
+use std::cell::{Cell, RefCell};
+use std::io::{self, BufRead, Cursor, Read};
+use std::rc::Rc;
+
+use synacor::{assemble, BlankLinePolicy, HaltReason, SharedBuffer, VM};
+
+/// Echoes every byte of input until it runs out.
+const ECHO: &str = "in r0\nout r0\njmp 0";
+
+fn echo(input: &str, configure: impl FnOnce(&mut VM)) -> Vec<u8> {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(input.as_bytes())
+        .output(Box::new(output.clone()))
+        .build();
+    configure(&mut vm);
+    vm.load(&assemble(ECHO).unwrap()).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    output.take()
+}
+
+#[test]
+fn carriage_returns_are_stripped_by_default() {
+    assert_eq!(echo("north\r\n", |_| ()), b"north\n");
+}
+
+#[test]
+fn carriage_returns_pass_through_when_not_normalizing() {
+    assert_eq!(
+        echo("north\r\n", |vm| vm.set_normalize_newlines(false)),
+        b"north\r\n"
+    );
+}
+
+/// Hands out one byte per `fill_buf`, like a terminal in raw mode, and
+/// shares how far it has been read.
+struct ByteReader {
+    bytes: Vec<u8>,
+    pos: Rc<Cell<usize>>,
+}
+
+impl Read for ByteReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl BufRead for ByteReader {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let pos = self.pos.get().min(self.bytes.len());
+        Ok(&self.bytes[pos..(pos + 1).min(self.bytes.len())])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.pos.set(self.pos.get() + amount);
+    }
+}
+
+#[test]
+fn raw_input_pulls_one_byte_per_in() {
+    let pos = Rc::new(Cell::new(0));
+    let reader = ByteReader {
+        bytes: b"abc".to_vec(),
+        pos: Rc::clone(&pos),
+    };
+    let mut vm = VM::builder()
+        .input(Box::new(reader))
+        .raw_input(true)
+        .output(Box::new(SharedBuffer::new()))
+        .build();
+    vm.load(&assemble("in r0\nin r1\nhalt").unwrap()).unwrap();
+
+    vm.step().unwrap();
+    assert_eq!(vm.registers()[0], u16::from(b'a'));
+    assert_eq!(pos.get(), 1);
+
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[1], u16::from(b'b'));
+    assert_eq!(pos.get(), 2);
+}
+
+#[test]
+fn macros_expand_whole_lines() {
+    assert_eq!(
+        echo("n\nnorth\n", |vm| vm.add_macro("n", "go north")),
+        b"go north\nnorth\n"
+    );
+}
+
+#[test]
+fn recorded_input_replays_to_the_same_output() {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .input(Box::new(Cursor::new(b"north\n".to_vec())))
+        .output(Box::new(output.clone()))
+        .build();
+    vm.load(&assemble(ECHO).unwrap()).unwrap();
+    vm.set_record_input(true);
+    vm.queue_input("take lamp\n");
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    assert_eq!(vm.consumed_input(), b"take lamp\nnorth\n");
+
+    assert_eq!(echo_replayed(vm.consumed_input()), output.take());
+}
+
+fn echo_replayed(recorded: &[u8]) -> Vec<u8> {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(recorded)
+        .output(Box::new(output.clone()))
+        .build();
+    vm.load(&assemble(ECHO).unwrap()).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    output.take()
+}
+
+#[test]
+fn queued_input_comes_before_scripts_and_scripts_before_stdin() {
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .input(Box::new(Cursor::new(b"stdin\n".to_vec())))
+        .output(Box::new(output.clone()))
+        .build();
+    vm.add_input_script(Box::new(Cursor::new(b"script\n".to_vec())));
+    vm.queue_input("queued\n");
+    vm.load(&assemble(ECHO).unwrap()).unwrap();
+
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    assert_eq!(output.take(), b"queued\nscript\nstdin\n");
+}
+
+#[test]
+fn blank_lines_are_delivered_by_default() {
+    assert_eq!(echo("look\n\nnorth\n", |_| ()), b"look\n\nnorth\n");
+}
+
+#[test]
+fn blank_lines_can_end_the_input() {
+    assert_eq!(
+        echo("look\n\nnorth\n", |vm| vm
+            .set_blank_line_policy(BlankLinePolicy::EndInput)),
+        b"look\n"
+    );
+}
+
+#[test]
+fn a_mapped_prompt_is_answered_with_its_response() {
+    // Prints `?`, then echoes one line and halts.
+    let source = "out 63\nout 10\nin r0\nout r0\neq r1 r0 10\njf r1 4\nhalt";
+    let output = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(b"")
+        .output(Box::new(output.clone()))
+        .build();
+    vm.add_prompt_response("!", "wrong");
+    vm.add_prompt_response("?", "look");
+    vm.load(&assemble(source).unwrap()).unwrap();
+
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.take(), b"?\nlook\n");
+}
+
+#[test]
+fn with_input_reads_lines_from_the_given_reader() {
+    let mut vm = VM::with_input(Box::new(Cursor::new("take lantern\ngo north\n")));
+    // Output still goes to stdout; the callback sees the same bytes.
+    let echoed = Rc::new(RefCell::new(Vec::new()));
+    let sink = echoed.clone();
+    vm.set_on_output(Box::new(move |byte| sink.borrow_mut().push(byte)));
+    vm.set_record_input(true);
+    vm.load(&assemble(ECHO).unwrap()).unwrap();
+
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    assert_eq!(vm.consumed_input(), b"take lantern\ngo north\n");
+    assert_eq!(*echoed.borrow(), b"take lantern\ngo north\n");
+}
@@ -0,0 +1,23 @@
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+use synacor::{assemble, HaltReason, SharedBuffer, VM};
+
+#[test]
+fn raising_the_flag_mid_run_interrupts_it() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("jmp 0").unwrap()).unwrap();
+
+    let flag = vm.interrupt_flag();
+    let raiser = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(20));
+        flag.store(true, Ordering::Relaxed);
+    });
+    assert_eq!(vm.run().unwrap(), HaltReason::Interrupted);
+    raiser.join().unwrap();
+
+    assert!(vm.cycles() > 0);
+    assert!(!vm.interrupt_flag().load(Ordering::Relaxed));
+    assert_eq!(vm.pc(), 0);
+}
@@ -0,0 +1,72 @@
+use synacor::{ByteOrder, VmError, MEMORY_SIZE, VM};
+
+const WORDS: [u16; 4] = [19, 0x0102, 32768, 0];
+
+#[test]
+fn both_byte_orders_load_the_same_words() {
+    let little: Vec<u8> = WORDS.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let big: Vec<u8> = WORDS.iter().flat_map(|word| word.to_be_bytes()).collect();
+
+    let mut from_little = VM::new();
+    from_little.load(&little).unwrap();
+
+    let mut from_big = VM::new();
+    from_big.set_byte_order(ByteOrder::Big);
+    from_big.load(&big).unwrap();
+
+    let mut streamed = VM::new();
+    streamed.set_byte_order(ByteOrder::Big);
+    streamed.load_from(&big[..]).unwrap();
+
+    assert_eq!(from_little.memory()[..4], WORDS);
+    assert_eq!(from_big.memory(), from_little.memory());
+    assert_eq!(streamed.memory(), from_little.memory());
+}
+
+#[test]
+fn load_at_overlays_without_clearing_memory() {
+    let bytes: Vec<u8> = WORDS.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let mut vm = VM::new();
+    vm.load(&bytes).unwrap();
+    vm.load_at(&[7, 0, 8, 0], 100).unwrap();
+
+    assert_eq!(vm.memory()[..4], WORDS);
+    assert_eq!(vm.memory()[100..102], [7, 8]);
+    assert_eq!(vm.memory()[102], 0);
+}
+
+#[test]
+fn load_at_rejects_an_overlay_past_the_end() {
+    let mut vm = VM::new();
+    assert!(matches!(
+        vm.load_at(&[1, 0, 2, 0], MEMORY_SIZE - 1),
+        Err(VmError::OutOfBounds {
+            addr: MEMORY_SIZE,
+            op: None
+        })
+    ));
+    assert_eq!(vm.memory()[MEMORY_SIZE - 1], 0);
+
+    assert!(matches!(
+        vm.load_at(&[1, 0], usize::MAX),
+        Err(VmError::OutOfBounds {
+            addr: usize::MAX,
+            op: None
+        })
+    ));
+}
+
+#[test]
+fn load_words_copies_words_straight_into_memory() {
+    let mut vm = VM::new();
+    vm.load_words(&WORDS).unwrap();
+    assert_eq!(vm.memory()[..WORDS.len()], WORDS);
+    assert_eq!(vm.memory().len(), MEMORY_SIZE);
+
+    let mut vm = VM::new();
+    vm.load_words(&vec![21; MEMORY_SIZE]).unwrap();
+    assert!(matches!(
+        vm.load_words(&vec![21; MEMORY_SIZE + 1]),
+        Err(VmError::ProgramTooLarge(len)) if len == MEMORY_SIZE + 1
+    ));
+}
@@ -0,0 +1,33 @@
+mod common;
+
+use synacor::{HaltReason, VmError, VM};
+
+fn vm(source: &str) -> VM {
+    let mut vm = common::vm(source);
+    vm.set_loop_detection(100);
+    vm
+}
+
+#[test]
+fn a_jump_to_itself_is_detected() {
+    let mut vm = vm("noop\njmp 1");
+    assert!(matches!(
+        vm.run(),
+        Err(VmError::InfiniteLoopDetected { pc: 1 })
+    ));
+}
+
+#[test]
+fn a_counting_loop_is_not_a_false_positive() {
+    let mut vm = vm("set r0 0\nadd r0 r0 1\neq r1 r0 500\njf r1 3\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 500);
+}
+
+#[test]
+fn the_cycle_limit_stops_an_endless_loop() {
+    let mut vm = common::vm("jmp 0");
+    vm.set_max_cycles(1000);
+    assert!(matches!(vm.run(), Err(VmError::CycleLimitExceeded(1000))));
+    assert_eq!(vm.cycles(), 1000);
+}
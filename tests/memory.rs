@@ -0,0 +1,35 @@
+mod common;
+
+use common::vm;
+use synacor::{HaltReason, Op, VmError};
+
+#[test]
+fn wmem_and_rmem_within_memory_succeed() {
+    let mut vm = vm("wmem 200 7\nrmem r0 200\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.memory()[200], 7);
+    assert_eq!(vm.registers()[0], 7);
+}
+
+#[test]
+fn wmem_past_the_end_of_memory_is_an_error() {
+    // r0 picks up 40000 from address 9, which is not a valid address.
+    let mut vm = vm("rmem r0 9\nwmem r0 1\nhalt");
+    vm.poke_mem(9, 40000).unwrap();
+    assert!(matches!(
+        vm.run(),
+        Err(VmError::OutOfBounds {
+            addr: 40000,
+            op: Some(Op::Wmem),
+        })
+    ));
+}
+
+#[test]
+fn the_last_address_can_be_written_and_read() {
+    let mut vm = vm("wmem 32767 42\nrmem r0 32767\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.memory().len(), 32768);
+    assert_eq!(vm.memory()[32767], 42);
+    assert_eq!(vm.registers()[0], 42);
+}
@@ -0,0 +1,38 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use synacor::{assemble, HaltReason, Instruction, Op, SharedBuffer, VmObserver, VM};
+
+/// Counts the hooks it sees, and the opcodes `before_step` was handed.
+#[derive(Clone, Default)]
+struct Counter {
+    before: Rc<RefCell<Vec<Op>>>,
+    after: Rc<RefCell<usize>>,
+}
+
+impl VmObserver for Counter {
+    fn before_step(&mut self, _vm: &VM, inst: &Instruction) {
+        self.before.borrow_mut().push(inst.op);
+    }
+
+    fn after_step(&mut self, _vm: &VM) {
+        *self.after.borrow_mut() += 1;
+    }
+}
+
+#[test]
+fn an_observer_sees_every_instruction() {
+    let counter = Counter::default();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("set r0 0\nadd r0 r0 1\neq r1 r0 3\njf r1 3\nhalt").unwrap())
+        .unwrap();
+    vm.add_observer(Box::new(counter.clone()));
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    // set, three rounds of add/eq/jf, then halt.
+    assert_eq!(counter.before.borrow().len(), 11);
+    assert_eq!(*counter.after.borrow(), 11);
+    assert_eq!(counter.before.borrow()[..2], [Op::Set, Op::Add]);
+    assert_eq!(counter.before.borrow().last(), Some(&Op::Halt));
+    assert_eq!(vm.cycles(), 11);
+}
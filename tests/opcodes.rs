@@ -0,0 +1,302 @@
+mod common;
+
+use common::load;
+use synacor::{
+    HaltReason, Instruction, Op, SharedBuffer, StepResult, UnknownOpPolicy, VmError, MEMORY_SIZE,
+    OPCODES, VM,
+};
+
+/// Runs `source`, which must end by halting, and returns the VM afterwards.
+fn run(source: &str) -> VM {
+    let (mut vm, _) = load(source);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    vm
+}
+
+#[test]
+fn halt_stops_the_run() {
+    let (mut vm, _) = load("halt\nset r0 1");
+    assert_eq!(vm.step().unwrap(), StepResult::Stopped(HaltReason::Halted));
+    assert_eq!(vm.registers()[0], 0);
+    assert_eq!(vm.cycles(), 1);
+}
+
+#[test]
+fn soft_halt_runs_every_stage() {
+    let (mut vm, output) = load("out 65\nhalt\nout 66\nhalt");
+    vm.set_soft_halt(true);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b"AB");
+
+    let (mut vm, output) = load("out 65\nhalt\nout 66\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b"A");
+}
+
+#[test]
+fn set_writes_a_literal_or_register() {
+    let vm = run("set r3 42\nset r5 r3\nhalt");
+    assert_eq!(vm.registers()[3], 42);
+    assert_eq!(vm.registers()[5], 42);
+}
+
+#[test]
+fn set_rejects_a_literal_destination() {
+    let (mut vm, _) = load("set 3 42");
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::InvalidInstruction { addr: 0, .. })
+    ));
+}
+
+#[test]
+fn strict_decode_rejects_a_literal_destination_before_running() {
+    let (mut vm, _) = load("set 3 42");
+    vm.set_strict_decode(true);
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::ExpectedRegister {
+            op: Op::Set,
+            operand: 0
+        })
+    ));
+    assert_eq!(vm.cycles(), 0);
+}
+
+#[test]
+fn push_and_pop_are_last_in_first_out() {
+    let vm = run("push 7\npush 9\npop r0\nhalt");
+    assert_eq!(vm.registers()[0], 9);
+    assert_eq!(vm.stack(), [7]);
+}
+
+#[test]
+fn pop_on_an_empty_stack_fails() {
+    let (mut vm, _) = load("pop r0");
+    assert!(matches!(vm.step(), Err(VmError::EmptyStackPop)));
+}
+
+#[test]
+fn eq_produces_one_or_zero() {
+    let vm = run("eq r0 5 5\neq r1 5 6\nhalt");
+    assert_eq!(vm.registers()[..2], [1, 0]);
+}
+
+#[test]
+fn gt_produces_one_or_zero() {
+    let vm = run("gt r0 6 5\ngt r1 5 5\ngt r2 4 5\nhalt");
+    assert_eq!(vm.registers()[..3], [1, 0, 0]);
+}
+
+#[test]
+fn gt_compares_unsigned_across_16384() {
+    let vm = run("gt r0 16384 16383\ngt r1 16383 16384\ngt r2 32767 16384\nhalt");
+    assert_eq!(vm.registers()[..3], [1, 0, 1]);
+}
+
+#[test]
+fn jmp_moves_pc() {
+    let (mut vm, _) = load("jmp 5\nset r0 1\nhalt");
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 5);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 0);
+}
+
+#[test]
+fn jt_jumps_only_on_nonzero() {
+    let (mut vm, _) = load("jt 0 9\njt 3 9");
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 3);
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 9);
+}
+
+#[test]
+fn jf_jumps_only_on_zero() {
+    let (mut vm, _) = load("jf 3 9\njf 0 9");
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 3);
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 9);
+}
+
+#[test]
+fn add_wraps_modulo_32768() {
+    let vm = run("add r0 32758 15\nadd r1 32767 32767\nhalt");
+    assert_eq!(vm.registers()[..2], [5, 32766]);
+}
+
+#[test]
+fn mult_widens_before_the_modulus() {
+    let vm = run("mult r0 32767 32767\nmult r1 200 300\nhalt");
+    assert_eq!(vm.registers()[..2], [1, 60000 % 32768]);
+}
+
+#[test]
+fn mod_takes_the_remainder() {
+    let vm = run("mod r0 17 5\nmod r1 4 9\nhalt");
+    assert_eq!(vm.registers()[..2], [2, 4]);
+}
+
+#[test]
+fn mod_by_zero_fails() {
+    let (mut vm, _) = load("mod r0 17 0");
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::ModByZero {
+            addr: 0,
+            dividend: 17
+        })
+    ));
+}
+
+#[test]
+fn and_and_or_are_bitwise() {
+    let vm = run("and r0 12 10\nor r1 12 10\nhalt");
+    assert_eq!(vm.registers()[..2], [8, 14]);
+}
+
+#[test]
+fn not_complements_15_bits() {
+    let vm = run("not r0 0\nnot r1 21845\nnot r2 32767\nhalt");
+    assert_eq!(vm.registers()[..3], [32767, 10922, 0]);
+}
+
+#[test]
+fn wmem_and_rmem_round_trip_through_memory() {
+    let vm = run("set r1 100\nwmem r1 1234\nrmem r0 100\nhalt");
+    assert_eq!(vm.memory()[100], 1234);
+    assert_eq!(vm.registers()[0], 1234);
+}
+
+#[test]
+fn call_pushes_the_return_address_and_ret_pops_it() {
+    // 0: call 4, 2: halt, 3: noop, 4: set r0 1, 7: ret
+    let (mut vm, _) = load("call 4\nhalt\nnoop\nset r0 1\nret");
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 4);
+    assert_eq!(vm.stack(), [2]);
+
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 2);
+    assert!(vm.stack().is_empty());
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 1);
+}
+
+#[test]
+fn ret_on_an_empty_stack_stops() {
+    let (mut vm, _) = load("ret");
+    assert_eq!(vm.run().unwrap(), HaltReason::EmptyRetStack);
+}
+
+#[test]
+fn out_writes_a_character() {
+    let (mut vm, output) = load("out 104\nset r0 105\nout r0\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b"hi");
+}
+
+#[test]
+fn out_writes_bytes_above_ascii_unchanged() {
+    let (mut vm, output) = load("out 255\nout 128\nhalt");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), [0xFF, 0x80]);
+}
+
+#[test]
+fn in_reads_one_byte_at_a_time() {
+    let (mut vm, _) = load("in r0\nin r1\nhalt");
+    vm.queue_input("x\n");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[..2], [u16::from(b'x'), u16::from(b'\n')]);
+}
+
+#[test]
+fn in_delivers_multi_byte_characters_a_byte_at_a_time() {
+    let (mut vm, _) = load("in r0\nin r1\nin r2\nhalt");
+    vm.queue_input("é\n");
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[..3], [0xC3, 0xA9, u16::from(b'\n')]);
+}
+
+#[test]
+fn noop_only_advances_pc() {
+    let (mut vm, _) = load("noop\nhalt");
+    vm.step().unwrap();
+    assert_eq!(vm.pc(), 1);
+    assert_eq!(vm.registers(), &[0; 8]);
+    assert!(vm.stack().is_empty());
+}
+
+#[test]
+fn running_off_the_end_of_memory_is_distinct_from_halting() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load_words(&[21; MEMORY_SIZE]).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::ReachedMemoryEnd);
+    assert_eq!(vm.pc(), MEMORY_SIZE);
+}
+
+#[test]
+fn an_instruction_cut_off_by_the_end_of_memory_is_out_of_bounds() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.poke_mem(MEMORY_SIZE - 1, 19).unwrap();
+    vm.load_words(&[6, MEMORY_SIZE as u16 - 1]).unwrap();
+    vm.step().unwrap();
+    assert!(matches!(
+        vm.step(),
+        Err(VmError::OutOfBounds {
+            addr: MEMORY_SIZE,
+            op: Some(Op::Out),
+        })
+    ));
+    assert_eq!(vm.current_pc(), MEMORY_SIZE - 1);
+}
+
+#[test]
+fn unknown_opcodes_abort_by_default() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load_words(&[9999, 0]).unwrap();
+    assert!(matches!(vm.step(), Err(VmError::UnknownOpcode(9999))));
+}
+
+#[test]
+fn skip_word_resumes_at_the_next_instruction() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.set_unknown_op_policy(UnknownOpPolicy::SkipWord);
+    vm.load_words(&[9999, 30000, 1, 32768, 5, 0]).unwrap();
+    assert_eq!(vm.step().unwrap(), StepResult::Continue);
+    assert_eq!(vm.pc(), 1);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 5);
+    assert_eq!(vm.cycles(), 2);
+}
+
+#[test]
+fn noop_policy_executes_unknown_opcodes_as_noop() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.set_unknown_op_policy(UnknownOpPolicy::Noop);
+    vm.load_words(&[9999, 1, 32768, 5, 0]).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 5);
+    assert_eq!(vm.cycles(), 3);
+}
+
+#[test]
+fn the_opcode_table_is_complete_and_drives_decoding() {
+    assert_eq!(OPCODES.len(), 22);
+    for (index, spec) in OPCODES.iter().enumerate() {
+        assert_eq!(usize::from(spec.code), index);
+        assert_eq!(spec.op as usize, index);
+        assert_eq!(Op::try_from(spec.code).unwrap(), spec.op);
+        assert_eq!(spec.op.spec().mnemonic, spec.mnemonic);
+
+        let (inst, next) = Instruction::decode(&[spec.code, 1, 2, 3], 0).unwrap();
+        assert_eq!(inst.op, spec.op);
+        assert_eq!(next, 1 + spec.arity);
+        assert_eq!(inst.len(), 1 + spec.arity);
+    }
+    assert!(matches!(Op::try_from(22), Err(VmError::UnknownOpcode(22))));
+}
@@ -0,0 +1,137 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use synacor::{assemble, HaltReason, SharedBuffer, Tee, VM};
+
+/// Records how many bytes had been written at each flush.
+#[derive(Clone, Default)]
+struct FlushLog {
+    written: Rc<RefCell<usize>>,
+    flushes: Rc<RefCell<Vec<usize>>>,
+}
+
+impl Write for FlushLog {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        *self.written.borrow_mut() += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flushes.borrow_mut().push(*self.written.borrow());
+        Ok(())
+    }
+}
+
+fn run(flush_on_newline: bool) -> Vec<usize> {
+    let log = FlushLog::default();
+    let mut vm = VM::with_output(Box::new(log.clone()));
+    vm.set_flush_on_newline(flush_on_newline);
+    vm.load(&assemble("out 104\nout 10\nout 63\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    log.flushes.take()
+}
+
+#[test]
+fn output_is_flushed_after_each_newline() {
+    assert_eq!(run(true), [2]);
+}
+
+#[test]
+fn flushing_on_newline_can_be_turned_off() {
+    assert!(run(false).is_empty());
+}
+
+#[test]
+fn wmem_into_the_mmio_output_window_emits_a_byte() {
+    let output = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(output.clone()));
+    vm.set_mmio_output(32000..32001);
+    vm.load(&assemble("wmem 32000 104\nwmem 32000 105\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b"hi");
+    assert_eq!(vm.memory()[32000], 0);
+}
+
+#[test]
+fn rmem_from_the_mmio_input_window_consumes_input() {
+    let mut vm = VM::replay(b"ab");
+    vm.set_mmio_input(32000..32010);
+    vm.load(&assemble("rmem r0 32000\nrmem r1 32009\nrmem r2 32000\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::OutOfInput);
+    assert_eq!(vm.registers()[..2], [u16::from(b'a'), u16::from(b'b')]);
+}
+
+#[test]
+fn echoed_input_is_interleaved_with_output() {
+    let output = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(output.clone()));
+    vm.set_echo_input(true);
+    vm.queue_input("go\n");
+    let source = "out 62\nin r0\nin r0\nin r0\nout 111\nout 107\nhalt";
+    vm.load(&assemble(source).unwrap()).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(output.contents(), b">go\nok");
+}
+
+#[test]
+fn tee_writes_identical_bytes_to_both_sinks() {
+    let (first, second) = (SharedBuffer::new(), SharedBuffer::new());
+    let mut vm = VM::builder()
+        .replay(b"")
+        .output(Box::new(Tee::new(first.clone(), second.clone())))
+        .build();
+    vm.load(&assemble("out 104\nout 105\nout 10\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(first.contents(), b"hi\n");
+    assert_eq!(second.contents(), first.contents());
+}
+
+#[test]
+fn tee_copies_what_is_read() {
+    let copy = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .input(Box::new(Tee::new(&b"go\n"[..], copy.clone())))
+        .output(Box::new(SharedBuffer::new()))
+        .build();
+    vm.load(&assemble("in r0\nin r1\nin r2\nhalt").unwrap())
+        .unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(copy.contents(), b"go\n");
+}
+
+#[test]
+fn the_output_callback_sees_every_byte_written() {
+    let output = SharedBuffer::new();
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let mut vm = VM::with_output(Box::new(output.clone()));
+    vm.load(&assemble("out 72\nout 105\nout 10\nhalt").unwrap())
+        .unwrap();
+    let sink = seen.clone();
+    vm.set_on_output(Box::new(move |byte| sink.borrow_mut().push(byte)));
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(*seen.borrow(), b"Hi\n");
+    assert_eq!(output.contents(), b"Hi\n");
+}
+
+#[test]
+fn the_io_log_stamps_reads_after_the_writes_before_them() {
+    let log = SharedBuffer::new();
+    let mut vm = VM::builder()
+        .replay(b"x")
+        .output(Box::new(SharedBuffer::new()))
+        .build();
+    vm.load(&assemble("out 62\nout 32\nin r0\nout r0\nhalt").unwrap())
+        .unwrap();
+    vm.set_io_log(Box::new(log.clone()));
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    let log = String::from_utf8(log.contents()).unwrap();
+    assert_eq!(log, "1 out 62\n2 out 32\n3 in 120\n4 out 120\n");
+}
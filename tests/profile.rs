@@ -0,0 +1,89 @@
+use synacor::{assemble, HaltReason, SharedBuffer, VM};
+
+/// Counts r0 from 1 to 10 with one `add`, `eq` and `jf` per iteration.
+const COUNTING_LOOP: &str = "set r0 0\nadd r0 r0 1\neq r1 r0 10\njf r1 3\nhalt";
+
+#[test]
+fn opcode_histogram_counts_each_executed_opcode() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTING_LOOP).unwrap()).unwrap();
+    vm.set_count_opcodes(true);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    let histogram = vm.opcode_histogram();
+    assert_eq!(histogram[..3].iter().map(|(_, runs)| runs).sum::<u64>(), 30);
+    assert!(histogram.contains(&("add".to_string(), 10)));
+    assert!(histogram.contains(&("jf".to_string(), 10)));
+    assert!(histogram.contains(&("set".to_string(), 1)));
+    assert_eq!(histogram.len(), 5);
+}
+
+#[test]
+fn opcode_histogram_is_empty_unless_counting() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTING_LOOP).unwrap()).unwrap();
+    vm.run().unwrap();
+    assert!(vm.opcode_histogram().is_empty());
+}
+
+#[test]
+fn reset_metrics_leaves_only_the_second_phase_counted() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("noop\nnoop\nset r0 1\nhalt").unwrap())
+        .unwrap();
+    vm.set_count_addresses(true);
+    vm.set_count_opcodes(true);
+    vm.step_n(2).unwrap();
+
+    vm.reset_metrics();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(vm.cycles(), 2);
+    assert_eq!(vm.executed_addresses(), [2, 5]);
+    assert_eq!(vm.hot_addresses(), [(2, 1), (5, 1)]);
+    let histogram = vm.opcode_histogram();
+    assert_eq!(histogram.len(), 2);
+    assert!(!histogram.iter().any(|(mnemonic, _)| mnemonic == "noop"));
+    assert_eq!(vm.registers()[0], 1);
+}
+
+#[test]
+fn coverage_marks_exactly_the_executed_opcodes() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTING_LOOP).unwrap()).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(vm.executed_addresses(), [0, 3, 7, 11, 14]);
+    assert_eq!(vm.coverage_ratio(), 5.0 / 32768.0);
+}
+
+#[test]
+fn hot_addresses_count_each_loop_iteration() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTING_LOOP).unwrap()).unwrap();
+    vm.set_count_addresses(true);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(
+        vm.hot_addresses(),
+        [(3, 10), (7, 10), (11, 10), (0, 1), (14, 1)]
+    );
+}
+
+#[test]
+fn the_register_csv_has_a_header_and_a_row_per_instruction() {
+    let csv = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("set r0 3\nadd r1 r0 4\nhalt").unwrap())
+        .unwrap();
+    vm.set_reg_csv(Box::new(csv.clone())).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(
+        String::from_utf8(csv.contents()).unwrap(),
+        "cycle,r0,r1,r2,r3,r4,r5,r6,r7\n\
+         1,3,0,0,0,0,0,0,0\n\
+         2,3,7,0,0,0,0,0,0\n\
+         3,3,7,0,0,0,0,0,0\n"
+    );
+}
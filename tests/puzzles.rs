@@ -0,0 +1,26 @@
+use synacor::puzzles::{solve_coins, solve_orb_grid, Direction, OrbCell};
+
+#[test]
+fn coins_are_ordered_to_satisfy_the_equation() {
+    assert_eq!(solve_coins(&[2, 3, 5, 7, 9]), Some([9, 2, 5, 7, 3]));
+    assert_eq!(solve_coins(&[1, 1, 1, 1, 1]), None);
+}
+
+#[test]
+fn the_orb_grid_has_a_twelve_step_walk() {
+    use Direction::*;
+    use OrbCell::*;
+
+    let grid = [
+        [Mul, Number(8), Sub, Number(1)],
+        [Number(4), Mul, Number(11), Mul],
+        [Add, Number(4), Sub, Number(18)],
+        [Number(22), Sub, Number(9), Mul],
+    ];
+    assert_eq!(
+        solve_orb_grid(&grid),
+        Some(vec![
+            North, East, East, North, West, South, East, East, West, North, North, East
+        ])
+    );
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use synacor::VM;
+
+/// 0: set r0 1, 3: add r0 r0 1, 7: push r0, 9: noop, 10: out 33, 12: halt
+const PROGRAM: &str = "set r0 1\nadd r0 r0 1\npush r0\nnoop\nout 33\nhalt";
+
+fn vm() -> VM {
+    let mut vm = common::vm(PROGRAM);
+    vm.set_rewind_depth(100);
+    vm
+}
+
+#[test]
+fn history_lists_the_last_instructions_in_order() {
+    let mut vm = vm();
+    vm.step_n(5).unwrap();
+    assert_eq!(
+        vm.history(3),
+        ["0007: push r0", "0009: noop", "0010: out 33"]
+    );
+    assert_eq!(vm.history(100).len(), 5);
+}
+
+#[test]
+fn step_back_restores_the_earlier_state_exactly() {
+    // 0: set r0 5, 3: wmem 100 r0, 6: push r0, 8: add r0 r0 1, 12: halt
+    let mut vm = common::vm("set r0 5\nwmem 100 r0\npush r0\nadd r0 r0 1\nhalt");
+    vm.set_rewind_depth(10);
+
+    vm.step().unwrap();
+    let (registers, stack, pc, cycles) =
+        (*vm.registers(), vm.stack().to_vec(), vm.pc(), vm.cycles());
+    let memory = vm.memory().to_vec();
+
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.memory()[100], 5);
+    assert_eq!(vm.stack(), [5]);
+
+    vm.step_back().unwrap();
+    vm.step_back().unwrap();
+    assert_eq!(*vm.registers(), registers);
+    assert_eq!(vm.stack(), stack);
+    assert_eq!(vm.pc(), pc);
+    assert_eq!(vm.cycles(), cycles);
+    assert_eq!(vm.memory(), memory);
+
+    vm.step_back().unwrap();
+    assert_eq!(vm.pc(), 0);
+    assert!(vm.step_back().is_err());
+}
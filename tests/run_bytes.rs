@@ -0,0 +1,46 @@
+use synacor::{assemble, run_bytes, VmError, RUN_BYTES_MAX_CYCLES};
+
+#[test]
+fn echoes_input_back() {
+    let program = assemble("in r0\nout r0\nin r0\nout r0\nhalt").unwrap();
+    assert_eq!(run_bytes(&program, b"ok").unwrap(), b"ok");
+}
+
+#[test]
+fn rejects_a_truncated_word() {
+    assert!(matches!(
+        run_bytes(&[19, 0, 65], b""),
+        Err(VmError::OddProgramLength(3))
+    ));
+}
+
+#[test]
+fn stops_an_endless_loop() {
+    let program = assemble("jmp 0").unwrap();
+    assert!(matches!(
+        run_bytes(&program, b""),
+        Err(VmError::CycleLimitExceeded(RUN_BYTES_MAX_CYCLES))
+    ));
+}
+
+#[test]
+fn survives_malformed_programs() {
+    let cases: [&[u16]; 6] = [
+        // an operand that is neither a literal nor a register
+        &[19, 40000],
+        // an unknown opcode
+        &[22],
+        // pop on an empty stack
+        &[3, 32768],
+        // a literal destination
+        &[1, 5, 5],
+        // mod by zero
+        &[11, 32768, 1, 0],
+        // in with no input left, then ret on an empty stack
+        &[20, 32768, 18],
+    ];
+    for words in cases {
+        let program: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        let _ = run_bytes(&program, b"");
+    }
+}
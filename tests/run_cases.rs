@@ -0,0 +1,16 @@
+use synacor::{assemble, run_cases, CaseResult};
+
+#[test]
+fn reports_passes_and_the_first_differing_byte() {
+    // Echoes two bytes of input after a prompt.
+    let program = assemble("out 62\nin r0\nout r0\nin r0\nout r0\nhalt").unwrap();
+    let cases: [(&[u8], &[u8]); 3] = [(b"hi", b">hi"), (b"ho", b">hi"), (b"h", b">hi")];
+
+    let results = run_cases(&program, &cases);
+    assert!(matches!(results[0], CaseResult::Pass));
+    assert!(matches!(
+        &results[1],
+        CaseResult::Mismatch { offset: 2, output } if output == b">ho"
+    ));
+    assert!(matches!(results[2], CaseResult::Mismatch { offset: 2, .. }));
+}
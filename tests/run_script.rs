@@ -0,0 +1,16 @@
+use synacor::{assemble, SharedBuffer, VM};
+
+/// Prints `> ` and echoes each line back until the input runs out.
+const PROMPT_ECHO: &str = "out 62\nout 32\nin r0\nout r0\neq r1 r0 10\njt r1 0\njmp 4";
+
+#[test]
+fn returns_everything_printed_for_the_commands() {
+    let output = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(output.clone()));
+    vm.load(&assemble(PROMPT_ECHO).unwrap()).unwrap();
+
+    let printed = vm.run_script("look\ngo north\n").unwrap();
+    assert_eq!(printed, "> look\n> go north\n> ");
+    assert!(printed.contains("go north"));
+    assert!(output.contents().is_empty());
+}
@@ -0,0 +1,31 @@
+use synacor::{run_source, VmError, RUN_SOURCE_MAX_CYCLES};
+
+#[test]
+fn runs_an_inline_program() {
+    assert_eq!(
+        run_source("out 104\nout 105\nout 10\nhalt", "").unwrap(),
+        "hi\n"
+    );
+}
+
+#[test]
+fn feeds_the_input_to_the_program() {
+    assert_eq!(
+        run_source("in r0\nadd r0 r0 1\nout r0\nhalt", "a").unwrap(),
+        "b"
+    );
+}
+
+#[test]
+fn reports_assembly_errors() {
+    assert!(run_source("frobnicate r0", "").is_err());
+}
+
+#[test]
+fn stops_a_program_that_never_halts() {
+    let err = run_source("jmp 0", "").unwrap_err();
+    assert_eq!(
+        err,
+        VmError::CycleLimitExceeded(RUN_SOURCE_MAX_CYCLES).to_string()
+    );
+}
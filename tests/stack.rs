@@ -0,0 +1,81 @@
+mod common;
+
+use common::vm;
+use synacor::{HaltReason, SharedBuffer, VmError};
+
+#[test]
+fn unbounded_recursion_overflows_the_stack() {
+    let mut vm = vm("noop\ncall 1");
+    vm.set_max_stack_depth(10);
+    assert!(matches!(
+        vm.run(),
+        Err(VmError::StackOverflow { addr: 1, depth: 10 })
+    ));
+    assert_eq!(vm.stack().len(), 10);
+}
+
+#[test]
+fn the_backtrace_shows_nested_calls_most_recent_first() {
+    // 0: call 3, 2: halt, 3: push 9, 5: call 8, 7: ret, 8: noop, 9: ret
+    let mut vm = vm("call 3\nhalt\npush 9\ncall 8\nret\nnoop\nret");
+    vm.add_breakpoint(8);
+    assert_eq!(
+        vm.run_until_break().unwrap(),
+        HaltReason::Breakpoint { addr: 8 }
+    );
+    assert_eq!(vm.backtrace(), [7, 2]);
+    assert_eq!(vm.stack(), [2, 9, 7]);
+
+    vm.step().unwrap();
+    vm.step().unwrap();
+    assert_eq!(vm.backtrace(), [2]);
+}
+
+#[test]
+fn depth_samples_rise_and_fall_with_nested_calls() {
+    // 0: call 4, 2: halt, 3: noop, 4: call 7, 6: ret, 7: noop, 8: ret
+    let samples = SharedBuffer::new();
+    let mut vm = vm("call 4\nhalt\nnoop\ncall 7\nret\nnoop\nret");
+    vm.set_depth_profile(Box::new(samples.clone()), 1).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(
+        String::from_utf8(samples.contents()).unwrap(),
+        "cycle,depth\n1,1\n2,2\n3,2\n4,1\n5,0\n6,0\n"
+    );
+}
+
+#[test]
+fn the_call_log_indents_entries_and_exits_by_depth() {
+    let log = SharedBuffer::new();
+    let mut vm = vm("call 4\nhalt\nnoop\ncall 7\nret\nnoop\nret");
+    vm.set_call_log(Box::new(log.clone()));
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+
+    assert_eq!(
+        String::from_utf8(log.contents()).unwrap(),
+        "enter 4\n  enter 7\n  exit 7\nexit 4\n"
+    );
+}
+
+#[test]
+fn a_push_and_ret_jump_inside_a_call_keeps_the_caller_frame() {
+    // 0: call 3, 2: halt, 3: push 7, 5: ret, 6: halt, 7: noop, 8: ret
+    let log = SharedBuffer::new();
+    let mut vm = vm("call 3\nhalt\npush 7\nret\nhalt\nnoop\nret");
+    vm.set_call_log(Box::new(log.clone()));
+    vm.add_breakpoint(7);
+    assert_eq!(
+        vm.run_until_break().unwrap(),
+        HaltReason::Breakpoint { addr: 7 }
+    );
+    assert_eq!(vm.backtrace(), [2]);
+    assert_eq!(String::from_utf8(log.contents()).unwrap(), "enter 3\n");
+
+    assert_eq!(vm.run_until_break().unwrap(), HaltReason::Halted);
+    assert!(vm.backtrace().is_empty());
+    assert_eq!(
+        String::from_utf8(log.contents()).unwrap(),
+        "enter 3\nexit 3\n"
+    );
+}
@@ -0,0 +1,164 @@
+use std::{env, fs};
+
+use synacor::{assemble, diff_states, HaltReason, SharedBuffer, StateDiff, VmError, VmState, VM};
+
+#[test]
+fn state_round_trips_through_encoding() {
+    let mut vm = VM::new();
+    vm.load_words(&[1, 32768, 5, 0]).unwrap();
+    vm.set_register(3, 99).unwrap();
+
+    let state = vm.state();
+    assert_eq!(VmState::decode(&state.encode()).unwrap(), state);
+}
+
+#[test]
+fn diff_reports_exactly_what_changed() {
+    let mut vm = VM::new();
+    vm.load_words(&[1, 32768, 5, 0]).unwrap();
+    let before = VmState::decode(&vm.encode_state()).unwrap();
+
+    vm.set_register(7, 25734).unwrap();
+    vm.poke_mem(5489, 21).unwrap();
+    let after = VmState::decode(&vm.encode_state()).unwrap();
+
+    assert_eq!(
+        diff_states(&before, &after),
+        StateDiff {
+            registers: vec![(7, 0, 25734)],
+            memory: vec![(5489, 0, 21)],
+            pc: None,
+            stack: None,
+        }
+    );
+    assert!(diff_states(&after, &after).is_empty());
+}
+
+#[test]
+fn snapshot_is_written_once_at_the_first_in() {
+    let path = env::temp_dir().join(format!("synacor-first-in-{}.state", std::process::id()));
+    // 0: set r0 7, 3: in r1, 5: in r2, 7: halt
+    let mut vm = VM::builder()
+        .replay(b"ab")
+        .output(Box::new(SharedBuffer::new()))
+        .build();
+    vm.load(&assemble("set r0 7\nin r1\nin r2\nhalt").unwrap())
+        .unwrap();
+    vm.set_snapshot_at_input(path.clone());
+
+    vm.step().unwrap();
+    assert!(!path.exists());
+    vm.step().unwrap();
+    let first = fs::read(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert!(!path.exists());
+
+    let state = VmState::decode(&first).unwrap();
+    assert_eq!(state.pc, 3);
+    assert_eq!(state.registers[..3], [7, 0, 0]);
+}
+
+/// Counts r0 up to 1000 inside a call, pushing each value, so a few hundred
+/// steps leave a non-trivial stack, registers and call frame.
+const COUNTER: &str = "call 3\nhalt\nadd r0 r0 1\npush r0\neq r1 r0 1000\njf r1 3\nret";
+
+#[test]
+fn save_and_load_round_trip_into_a_fresh_vm() {
+    let path = env::temp_dir().join(format!("synacor-round-trip-{}.state", std::process::id()));
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTER).unwrap()).unwrap();
+    vm.step_n(300).unwrap();
+    vm.save_state(&path).unwrap();
+
+    let mut loaded = VM::with_output(Box::new(SharedBuffer::new()));
+    loaded.load_state(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.memory(), vm.memory());
+    assert_eq!(loaded.registers(), vm.registers());
+    assert_eq!(loaded.stack(), vm.stack());
+    assert_eq!(loaded.pc(), vm.pc());
+}
+
+#[test]
+fn loading_a_state_drops_the_old_call_frames_and_history() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(COUNTER).unwrap()).unwrap();
+    let fresh = vm.encode_state();
+    vm.set_rewind_depth(10);
+    vm.step_n(5).unwrap();
+    assert_eq!(vm.backtrace(), [2]);
+
+    vm.decode_state(&fresh).unwrap();
+    assert!(vm.backtrace().is_empty());
+    assert!(vm.stack().is_empty());
+    assert!(vm.step_back().is_err());
+}
+
+#[test]
+fn corrupted_headers_are_rejected_cleanly() {
+    let mut vm = VM::new();
+    vm.load_words(&[1, 32768, 5, 0]).unwrap();
+    let bytes = vm.encode_state();
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] ^= 0xff;
+    assert!(matches!(
+        vm.decode_state(&bad_magic),
+        Err(VmError::InvalidState(_))
+    ));
+
+    let mut bad_version = bytes.clone();
+    bad_version[4] = 99;
+    assert!(matches!(
+        vm.decode_state(&bad_version),
+        Err(VmError::UnsupportedStateVersion(99))
+    ));
+
+    assert!(matches!(
+        vm.decode_state(&bytes[..bytes.len() - 1]),
+        Err(VmError::InvalidState(_))
+    ));
+    assert_eq!(vm.memory()[..4], [1, 32768, 5, 0]);
+}
+
+#[test]
+fn reset_clears_execution_state_but_keeps_the_program() {
+    let program = assemble("set r0 7\npush r0\ncall 8\nhalt\nhalt").unwrap();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&program).unwrap();
+    vm.set_rewind_depth(10);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.stack(), [7, 7]);
+
+    vm.reset();
+    assert!(matches!(vm.step_back(), Err(VmError::NothingToRewind)));
+    assert_eq!(*vm.registers(), [0; 8]);
+    assert!(vm.stack().is_empty());
+    assert!(vm.backtrace().is_empty());
+    assert_eq!(vm.pc(), 0);
+    assert_eq!(vm.memory()[..9], [1, 32768, 7, 2, 32768, 17, 8, 0, 0]);
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 7);
+
+    vm.reset_all();
+    assert!(matches!(vm.step_back(), Err(VmError::NothingToRewind)));
+    assert!(vm.memory().iter().all(|&word| word == 0));
+}
+
+#[test]
+fn reset_drops_a_partly_read_input_line() {
+    let mut vm = VM::builder()
+        .replay(b"ab\ncd\n")
+        .output(Box::new(SharedBuffer::new()))
+        .build();
+    vm.load(&assemble("in r0\nhalt").unwrap()).unwrap();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], u16::from(b'a'));
+
+    vm.reset();
+    assert_eq!(vm.run().unwrap(), HaltReason::Halted);
+    assert_eq!(vm.registers()[0], u16::from(b'c'));
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use synacor::{assemble, timed, HaltReason, SharedBuffer, VmError, VM};
+
+#[test]
+fn timed_run_reports_both_phases() {
+    let program = assemble("set r0 0\nadd r0 r0 1\neq r1 r0 1000\njf r1 3\nhalt").unwrap();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+
+    let (reason, times) = vm.timed_run(&program).unwrap();
+    assert_eq!(reason, HaltReason::Halted);
+    assert_eq!(vm.registers()[0], 1000);
+    assert!(times.run > Duration::ZERO);
+    assert!(times.load + times.run < Duration::from_secs(10));
+}
+
+#[test]
+fn timed_run_passes_on_load_errors() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    assert!(vm.timed_run(&[1, 2, 3]).is_err());
+}
+
+#[test]
+fn timed_measures_the_closure() {
+    let ((), elapsed) = timed(|| std::thread::sleep(Duration::from_millis(5)));
+    assert!(elapsed >= Duration::from_millis(5));
+}
+
+#[test]
+fn a_time_limit_aborts_an_endless_loop() {
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble("jmp 0").unwrap()).unwrap();
+    vm.set_time_limit(Duration::from_millis(20));
+
+    assert!(matches!(
+        vm.run(),
+        Err(VmError::TimeLimitExceeded(limit)) if limit == Duration::from_millis(20)
+    ));
+    assert!(vm.cycles() > 0);
+}
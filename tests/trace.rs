@@ -0,0 +1,151 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use synacor::{assemble, SharedBuffer, TraceFormat, VM};
+
+fn trace(source: &str, format: TraceFormat) -> Vec<String> {
+    let trace = SharedBuffer::new();
+    let mut vm = VM::with_output(Box::new(SharedBuffer::new()));
+    vm.load(&assemble(source).unwrap()).unwrap();
+    vm.set_trace(Box::new(trace.clone()));
+    vm.set_trace_format(format);
+    vm.run().unwrap();
+    String::from_utf8(trace.contents())
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Just enough JSON to check the trace: every value it can contain.
+#[derive(Debug, PartialEq)]
+enum Json {
+    Number(u64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> &Json {
+        let Json::Object(fields) = self else {
+            panic!("{self:?} is not an object");
+        };
+        &fields.iter().find(|(name, _)| name == key).unwrap().1
+    }
+}
+
+/// Parses the whole of `text` as one JSON value, panicking on anything
+/// malformed or left over.
+fn parse(text: &str) -> Json {
+    let mut chars = text.chars().peekable();
+    let value = parse_value(&mut chars);
+    assert_eq!(chars.next(), None, "trailing input in {text}");
+    value
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Json {
+    match chars.peek() {
+        Some('"') => Json::String(parse_string(chars)),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            if chars.peek() == Some(&']') {
+                chars.next();
+                return Json::Array(items);
+            }
+            loop {
+                items.push(parse_value(chars));
+                match chars.next() {
+                    Some(',') => continue,
+                    Some(']') => return Json::Array(items),
+                    other => panic!("expected , or ] but got {other:?}"),
+                }
+            }
+        }
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+            if chars.peek() == Some(&'}') {
+                chars.next();
+                return Json::Object(fields);
+            }
+            loop {
+                let key = parse_string(chars);
+                assert_eq!(chars.next(), Some(':'));
+                fields.push((key, parse_value(chars)));
+                match chars.next() {
+                    Some(',') => continue,
+                    Some('}') => return Json::Object(fields),
+                    other => panic!("expected , or }} but got {other:?}"),
+                }
+            }
+        }
+        Some(ch) if ch.is_ascii_digit() => {
+            let mut digits = String::new();
+            while let Some(&ch) = chars.peek().filter(|ch| ch.is_ascii_digit()) {
+                digits.push(ch);
+                chars.next();
+            }
+            Json::Number(digits.parse().unwrap())
+        }
+        other => panic!("unexpected {other:?}"),
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> String {
+    assert_eq!(chars.next(), Some('"'));
+    let mut text = String::new();
+    loop {
+        match chars.next().expect("unterminated string") {
+            '"' => return text,
+            '\\' => match chars.next() {
+                Some('"') => text.push('"'),
+                Some('\\') => text.push('\\'),
+                Some('n') => text.push('\n'),
+                other => panic!("unsupported escape {other:?}"),
+            },
+            ch if ch.is_control() => panic!("raw control character in string"),
+            ch => text.push(ch),
+        }
+    }
+}
+
+#[test]
+fn json_lines_parse_back_into_their_fields() {
+    let lines = trace("set r0 5\nadd r1 r0 2\nhalt", TraceFormat::Json);
+    assert_eq!(lines.len(), 3);
+    let lines: Vec<Json> = lines.iter().map(|line| parse(line)).collect();
+
+    let add = &lines[1];
+    assert_eq!(add.get("pc"), &Json::Number(3));
+    assert_eq!(add.get("op"), &Json::String("add".to_string()));
+    let arg = |kind: &str, value| Json::Object(vec![(kind.to_string(), Json::Number(value))]);
+    assert_eq!(
+        add.get("args"),
+        &Json::Array(vec![
+            arg("register", 1),
+            arg("register", 0),
+            arg("literal", 2)
+        ])
+    );
+    assert_eq!(
+        add.get("regs"),
+        &Json::Array([5, 7, 0, 0, 0, 0, 0, 0].map(Json::Number).into())
+    );
+
+    assert_eq!(lines[2].get("op"), &Json::String("halt".to_string()));
+    assert_eq!(lines[2].get("args"), &Json::Array(Vec::new()));
+}
+
+#[test]
+fn text_lines_show_each_mnemonic_and_the_registers_it_touched() {
+    let lines = trace("set r0 5\nadd r1 r0 2\nout 65\nhalt", TraceFormat::Text);
+    assert_eq!(lines.len(), 4);
+    assert!(lines[0].starts_with("0000: set r0 5"));
+    assert!(lines[0].ends_with("r0=5"));
+    assert!(lines[1].starts_with("0003: add r1 r0 2"));
+    assert!(lines[1].contains("r1=7"));
+    assert!(lines[2].contains("out"));
+    assert!(!lines.iter().any(|line| line.contains('A')));
+}
@@ -0,0 +1,27 @@
+//! Native tests of the `wasm` wrapper; run with `--features wasm`.
+#![cfg(feature = "wasm")]
+
+use synacor::{assemble, WasmVm, STATUS_ERROR, STATUS_HALTED, STATUS_NEEDS_INPUT, STATUS_RUNNING};
+
+#[test]
+fn runs_a_program_through_buffers() {
+    let mut vm = WasmVm::new();
+    assert!(vm.load(&assemble("out 104\nin r0\nout r0\nhalt").unwrap()));
+
+    assert_eq!(vm.step(), STATUS_RUNNING);
+    assert_eq!(vm.take_output(), b"h");
+    assert_eq!(vm.run(100), STATUS_NEEDS_INPUT);
+
+    vm.push_input(b"i");
+    assert_eq!(vm.run(100), STATUS_HALTED);
+    assert_eq!(vm.take_output(), b"i");
+    assert_eq!(vm.registers()[0], u16::from(b'i'));
+}
+
+#[test]
+fn reports_errors_and_bad_images() {
+    let mut vm = WasmVm::new();
+    assert!(!vm.load(&[1, 2, 3]));
+    assert!(vm.load(&assemble("pop r0").unwrap()));
+    assert_eq!(vm.run(10), STATUS_ERROR);
+}